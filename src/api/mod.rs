@@ -1,14 +1,31 @@
-use std::sync::Arc;
+use std::{convert::Infallible, sync::Arc, time::Duration};
 
 use axum::{
-    extract::{Path, State},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
     http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
     routing::{get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
 
-use crate::{pubsub::ChainEvent, BitServWallet};
+use crate::{
+    config,
+    pubsub::{ChainEvent, EventFilter, SequencedEvent},
+    BitServWallet, CustomerAddress, Invoice, LedgerEntry, TransactionDetail, TransactionFilter,
+    TransactionSummary, Utxo, Withdrawal,
+};
 
 // Response types
 #[derive(Serialize)]
@@ -34,6 +51,193 @@ pub struct AddressesWithBalanceResponse {
     addresses: Vec<(String, f64)>,
 }
 
+#[derive(Serialize)]
+pub struct WithdrawalResponse {
+    success: bool,
+    txid: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct QueuedWithdrawalResponse {
+    success: bool,
+    id: Option<i64>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WithdrawalStatusResponse {
+    success: bool,
+    withdrawal: Option<Withdrawal>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CustomerAddressResponse {
+    success: bool,
+    customer: Option<CustomerAddress>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CustomerBalanceResponse {
+    success: bool,
+    customer_id: String,
+    balance: Option<i64>, // in sats
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CustomerHistoryResponse {
+    success: bool,
+    customer_id: String,
+    entries: Vec<LedgerEntry>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct InvoiceResponse {
+    success: bool,
+    invoice: Option<Invoice>,
+    uri: Option<String>, // BIP21
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TransactionsResponse {
+    success: bool,
+    transactions: Vec<TransactionSummary>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TransactionResponse {
+    success: bool,
+    transaction: Option<TransactionDetail>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UtxosResponse {
+    success: bool,
+    utxos: Vec<Utxo>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct FreezeResponse {
+    success: bool,
+    outpoint: String,
+    frozen: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EventsResponse {
+    success: bool,
+    events: Vec<SequencedEvent>,
+    error: Option<String>,
+}
+
+// Request types
+#[derive(Deserialize)]
+pub struct WithdrawalRequest {
+    address: String,
+    amount: u64,   // in sats
+    fee_rate: u64, // in sat/vB
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    #[serde(default)]
+    since: u64,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct CustomerAddressRequest {
+    #[serde(default)]
+    fresh: bool, // reveal a new address even if the customer already has one
+}
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    types: Option<String>, // comma separated event types, e.g. "dpsts,cdpsts"
+    address: Option<String>,
+}
+
+impl StreamQuery {
+    fn filter(self) -> EventFilter {
+        EventFilter {
+            kinds: self
+                .types
+                .unwrap_or_default()
+                .split(',')
+                .map(|kind| kind.trim().to_string())
+                .filter(|kind| !kind.is_empty())
+                .collect(),
+            address: self.address,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct QueueWithdrawalRequest {
+    address: String,
+    amount: u64,                 // in sats
+    customer_id: Option<String>, // debit the withdrawal from this customer's balance
+}
+
+#[derive(Deserialize)]
+pub struct CreateInvoiceRequest {
+    amount: u64,             // in sats
+    expires_in: Option<u64>, // in seconds, defaults to INVOICE_EXPIRY
+}
+
+#[derive(Deserialize)]
+pub struct TransactionsQuery {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    direction: Option<String>, // incoming, outgoing or self
+    status: Option<String>,    // confirmed or unconfirmed
+    min_height: Option<u32>,
+    max_height: Option<u32>,
+    address: Option<String>,
+}
+
+impl TransactionsQuery {
+    fn filter(&self, wallet: &BitServWallet) -> anyhow::Result<TransactionFilter> {
+        let confirmed = match self.status.as_deref() {
+            Some("confirmed") => Some(true),
+            Some("unconfirmed") => Some(false),
+            Some(status) => return Err(anyhow::anyhow!("Unknown status: {}", status)),
+            None => None,
+        };
+        Ok(TransactionFilter {
+            direction: self.direction.as_deref().map(str::parse).transpose()?,
+            confirmed,
+            min_height: self.min_height,
+            max_height: self.max_height,
+            script: self
+                .address
+                .as_deref()
+                .map(|address| wallet.address_script(address))
+                .transpose()?,
+        })
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct FreezeRequest {
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    limit: Option<u32>,
+}
+
 // Handler functions
 async fn get_balance(
     State(wallet): State<Arc<BitServWallet>>,
@@ -69,7 +273,7 @@ async fn get_new_address(
     State(wallet): State<Arc<BitServWallet>>,
 ) -> (StatusCode, Json<AddressResponse>) {
     println!("Getting new address");
-    let response = match wallet.reveal_next_address() {
+    match wallet.reveal_next_address() {
         Ok(address) => (
             StatusCode::OK,
             Json(AddressResponse {
@@ -86,8 +290,7 @@ async fn get_new_address(
                 error: Some(String::from("Error getting new address")),
             }),
         ),
-    };
-    response
+    }
 }
 
 async fn get_all_addresses(
@@ -114,11 +317,17 @@ async fn test_pub_deposits(
     Json(tx): Json<TestPubTxRequest>,
 ) -> (StatusCode, Json<TestPubTxResponse>) {
     println!("Testing public transaction");
-    let chain_event = ChainEvent::NewDeposits { deposits: tx.txs };
+    let customers = wallet
+        .customers_of(tx.txs.iter().map(|(address, _, _)| address.as_str()))
+        .unwrap_or_default();
+    let chain_event = ChainEvent::NewDeposits {
+        deposits: tx.txs,
+        customers,
+    };
 
     println!("Publishing event: {:?}", chain_event);
 
-    let response = match wallet.publish_chainevent(chain_event) {
+    match wallet.publish_chainevent(chain_event) {
         Ok(_) => (
             StatusCode::OK,
             Json(TestPubTxResponse {
@@ -133,8 +342,563 @@ async fn test_pub_deposits(
                 error: Some(String::from("Error testing public transaction")),
             }),
         ),
+    }
+}
+
+async fn get_transactions(
+    Query(query): Query<TransactionsQuery>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<TransactionsResponse>) {
+    println!("Getting transactions");
+    let filter = match query.filter(&wallet) {
+        Ok(filter) => filter,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(TransactionsResponse {
+                    success: false,
+                    transactions: Vec::new(),
+                    error: Some(format!("Invalid filter: {}", e)),
+                }),
+            )
+        }
     };
-    response
+    let limit = query.limit.unwrap_or(100).min(1000);
+    let transactions = wallet.list_transactions(&filter, query.offset, limit);
+    (
+        StatusCode::OK,
+        Json(TransactionsResponse {
+            success: true,
+            transactions,
+            error: None,
+        }),
+    )
+}
+
+async fn get_transaction(
+    Path(txid): Path<String>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<TransactionResponse>) {
+    println!("Getting transaction {}", txid);
+    match wallet.get_transaction(&txid) {
+        Ok(Some(transaction)) => (
+            StatusCode::OK,
+            Json(TransactionResponse {
+                success: true,
+                transaction: Some(transaction),
+                error: None,
+            }),
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(TransactionResponse {
+                success: false,
+                transaction: None,
+                error: Some(String::from("Transaction not found in wallet")),
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(TransactionResponse {
+                success: false,
+                transaction: None,
+                error: Some(format!("Invalid txid: {}", e)),
+            }),
+        ),
+    }
+}
+
+async fn get_utxos(State(wallet): State<Arc<BitServWallet>>) -> (StatusCode, Json<UtxosResponse>) {
+    println!("Getting utxos");
+    match wallet.list_utxos() {
+        Ok(utxos) => (
+            StatusCode::OK,
+            Json(UtxosResponse {
+                success: true,
+                utxos,
+                error: None,
+            }),
+        ),
+        Err(_e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(UtxosResponse {
+                success: false,
+                utxos: Vec::new(),
+                error: Some(String::from("Error getting utxos")),
+            }),
+        ),
+    }
+}
+
+async fn freeze_utxo(
+    Path(outpoint): Path<String>,
+    State(wallet): State<Arc<BitServWallet>>,
+    request: Option<Json<FreezeRequest>>,
+) -> (StatusCode, Json<FreezeResponse>) {
+    println!("Freezing utxo {}", outpoint);
+    let Json(request) = request.unwrap_or_default();
+    match wallet.freeze_utxo(&outpoint, request.reason.as_deref()) {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(FreezeResponse {
+                success: true,
+                outpoint,
+                frozen: true,
+                error: None,
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(FreezeResponse {
+                success: false,
+                outpoint,
+                frozen: false,
+                error: Some(format!("Error freezing utxo: {}", e)),
+            }),
+        ),
+    }
+}
+
+async fn unfreeze_utxo(
+    Path(outpoint): Path<String>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<FreezeResponse>) {
+    println!("Unfreezing utxo {}", outpoint);
+    match wallet.unfreeze_utxo(&outpoint) {
+        Ok(true) => (
+            StatusCode::OK,
+            Json(FreezeResponse {
+                success: true,
+                outpoint,
+                frozen: false,
+                error: None,
+            }),
+        ),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(FreezeResponse {
+                success: false,
+                outpoint,
+                frozen: false,
+                error: Some(String::from("Utxo is not frozen")),
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(FreezeResponse {
+                success: false,
+                outpoint,
+                frozen: false,
+                error: Some(format!("Error unfreezing utxo: {}", e)),
+            }),
+        ),
+    }
+}
+
+async fn create_withdrawal(
+    State(wallet): State<Arc<BitServWallet>>,
+    Json(request): Json<WithdrawalRequest>,
+) -> (StatusCode, Json<WithdrawalResponse>) {
+    println!("Creating withdrawal");
+    match wallet.send(&request.address, request.amount, request.fee_rate) {
+        Ok(txid) => (
+            StatusCode::OK,
+            Json(WithdrawalResponse {
+                success: true,
+                txid: Some(txid.to_string()),
+                error: None,
+            }),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(WithdrawalResponse {
+                success: false,
+                txid: None,
+                error: Some(format!("Error creating withdrawal: {}", e)),
+            }),
+        ),
+    }
+}
+
+async fn queue_withdrawal(
+    State(wallet): State<Arc<BitServWallet>>,
+    Json(request): Json<QueueWithdrawalRequest>,
+) -> (StatusCode, Json<QueuedWithdrawalResponse>) {
+    println!("Queueing withdrawal");
+    match wallet.queue_withdrawal(
+        &request.address,
+        request.amount,
+        request.customer_id.as_deref(),
+    ) {
+        Ok(id) => (
+            StatusCode::OK,
+            Json(QueuedWithdrawalResponse {
+                success: true,
+                id: Some(id),
+                error: None,
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(QueuedWithdrawalResponse {
+                success: false,
+                id: None,
+                error: Some(format!("Error queueing withdrawal: {}", e)),
+            }),
+        ),
+    }
+}
+
+async fn get_withdrawal(
+    Path(id): Path<i64>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<WithdrawalStatusResponse>) {
+    println!("Getting withdrawal");
+    match wallet.get_withdrawal(id) {
+        Ok(Some(withdrawal)) => (
+            StatusCode::OK,
+            Json(WithdrawalStatusResponse {
+                success: true,
+                withdrawal: Some(withdrawal),
+                error: None,
+            }),
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(WithdrawalStatusResponse {
+                success: false,
+                withdrawal: None,
+                error: Some(String::from("Withdrawal not found")),
+            }),
+        ),
+        Err(_e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(WithdrawalStatusResponse {
+                success: false,
+                withdrawal: None,
+                error: Some(String::from("Error getting withdrawal")),
+            }),
+        ),
+    }
+}
+
+async fn cancel_withdrawal(
+    Path(id): Path<i64>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<WithdrawalStatusResponse>) {
+    println!("Cancelling withdrawal");
+    match wallet.cancel_withdrawal(id) {
+        Ok(Some(withdrawal)) => (
+            StatusCode::OK,
+            Json(WithdrawalStatusResponse {
+                success: true,
+                withdrawal: Some(withdrawal),
+                error: None,
+            }),
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(WithdrawalStatusResponse {
+                success: false,
+                withdrawal: None,
+                error: Some(String::from("Withdrawal not found")),
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(WithdrawalStatusResponse {
+                success: false,
+                withdrawal: None,
+                error: Some(format!("Error cancelling withdrawal: {}", e)),
+            }),
+        ),
+    }
+}
+
+async fn get_events(
+    Query(query): Query<EventsQuery>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<EventsResponse>) {
+    println!("Getting events since {}", query.since);
+    let limit = query.limit.unwrap_or(100).min(1000);
+    match wallet.events_since(query.since, limit) {
+        Ok(events) => (
+            StatusCode::OK,
+            Json(EventsResponse {
+                success: true,
+                events,
+                error: None,
+            }),
+        ),
+        Err(_e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(EventsResponse {
+                success: false,
+                events: Vec::new(),
+                error: Some(String::from("Error getting events")),
+            }),
+        ),
+    }
+}
+
+async fn get_customer_address(
+    Path(customer_id): Path<String>,
+    State(wallet): State<Arc<BitServWallet>>,
+    request: Option<Json<CustomerAddressRequest>>,
+) -> (StatusCode, Json<CustomerAddressResponse>) {
+    println!("Getting address for customer {}", customer_id);
+    let Json(request) = request.unwrap_or_default();
+    match wallet.customer_address(&customer_id, request.fresh) {
+        Ok(customer) => (
+            StatusCode::OK,
+            Json(CustomerAddressResponse {
+                success: true,
+                customer: Some(customer),
+                error: None,
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(CustomerAddressResponse {
+                success: false,
+                customer: None,
+                error: Some(e.to_string()),
+            }),
+        ),
+    }
+}
+
+async fn get_customer_by_address(
+    Path(address): Path<String>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<CustomerAddressResponse>) {
+    println!("Looking up customer of address {}", address);
+    match wallet.find_customer_by_address(&address) {
+        Ok(Some(customer)) => (
+            StatusCode::OK,
+            Json(CustomerAddressResponse {
+                success: true,
+                customer: Some(customer),
+                error: None,
+            }),
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(CustomerAddressResponse {
+                success: false,
+                customer: None,
+                error: Some(String::from("Address is not assigned to a customer")),
+            }),
+        ),
+        Err(_e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(CustomerAddressResponse {
+                success: false,
+                customer: None,
+                error: Some(String::from("Error looking up customer")),
+            }),
+        ),
+    }
+}
+
+async fn get_customer_balance(
+    Path(customer_id): Path<String>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<CustomerBalanceResponse>) {
+    println!("Getting balance of customer {}", customer_id);
+    match wallet.customer_balance(&customer_id) {
+        Ok(balance) => (
+            StatusCode::OK,
+            Json(CustomerBalanceResponse {
+                success: true,
+                customer_id,
+                balance: Some(balance),
+                error: None,
+            }),
+        ),
+        Err(_e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(CustomerBalanceResponse {
+                success: false,
+                customer_id,
+                balance: None,
+                error: Some(String::from("Error getting customer balance")),
+            }),
+        ),
+    }
+}
+
+async fn get_customer_history(
+    Path(customer_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<CustomerHistoryResponse>) {
+    println!("Getting history of customer {}", customer_id);
+    let limit = query.limit.unwrap_or(100).min(1000);
+    match wallet.customer_history(&customer_id, limit) {
+        Ok(entries) => (
+            StatusCode::OK,
+            Json(CustomerHistoryResponse {
+                success: true,
+                customer_id,
+                entries,
+                error: None,
+            }),
+        ),
+        Err(_e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(CustomerHistoryResponse {
+                success: false,
+                customer_id,
+                entries: Vec::new(),
+                error: Some(String::from("Error getting customer history")),
+            }),
+        ),
+    }
+}
+
+async fn create_invoice(
+    State(wallet): State<Arc<BitServWallet>>,
+    Json(request): Json<CreateInvoiceRequest>,
+) -> (StatusCode, Json<InvoiceResponse>) {
+    println!("Creating invoice for {} sats", request.amount);
+    let expires_in = Duration::from_secs(request.expires_in.unwrap_or(config::invoice_expiry()));
+    match wallet.create_invoice(request.amount, expires_in) {
+        Ok(invoice) => (
+            StatusCode::OK,
+            Json(InvoiceResponse {
+                success: true,
+                uri: Some(invoice.uri()),
+                invoice: Some(invoice),
+                error: None,
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(InvoiceResponse {
+                success: false,
+                invoice: None,
+                uri: None,
+                error: Some(format!("Error creating invoice: {}", e)),
+            }),
+        ),
+    }
+}
+
+async fn get_invoice(
+    Path(id): Path<i64>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> (StatusCode, Json<InvoiceResponse>) {
+    println!("Getting invoice {}", id);
+    match wallet.get_invoice(id) {
+        Ok(Some(invoice)) => (
+            StatusCode::OK,
+            Json(InvoiceResponse {
+                success: true,
+                uri: Some(invoice.uri()),
+                invoice: Some(invoice),
+                error: None,
+            }),
+        ),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(InvoiceResponse {
+                success: false,
+                invoice: None,
+                uri: None,
+                error: Some(String::from("Invoice not found")),
+            }),
+        ),
+        Err(_e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(InvoiceResponse {
+                success: false,
+                invoice: None,
+                uri: None,
+                error: Some(String::from("Error getting invoice")),
+            }),
+        ),
+    }
+}
+
+async fn stream_events(
+    Query(query): Query<StreamQuery>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    println!("Streaming events over SSE");
+    let filter = query.filter();
+    let stream = BroadcastStream::new(wallet.subscribe_events()).filter_map(move |received| {
+        match received {
+            Ok(event) if filter.matches(&event.event) => {
+                let data = serde_json::to_string(&event).ok()?;
+                Some(Ok(Event::default()
+                    .id(event.seq.to_string())
+                    .event(event.event.kind())
+                    .data(data)))
+            }
+            Ok(_) => None,
+            // Missed events can be fetched from /events
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                eprintln!("SSE subscriber lagged behind by {} events", missed);
+                None
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn stream_events_ws(
+    ws: WebSocketUpgrade,
+    Query(query): Query<StreamQuery>,
+    State(wallet): State<Arc<BitServWallet>>,
+) -> Response {
+    println!("Streaming events over WebSocket");
+    let filter = query.filter();
+    let events = wallet.subscribe_events();
+    ws.on_upgrade(move |socket| forward_events(socket, events, filter))
+}
+
+async fn forward_events(
+    mut socket: WebSocket,
+    mut events: broadcast::Receiver<SequencedEvent>,
+    filter: EventFilter,
+) {
+    loop {
+        let event = tokio::select! {
+            received = events.recv() => match received {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    eprintln!("WebSocket subscriber lagged behind by {} events", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // Read the socket too, so closes and dropped connections end the stream
+            // without waiting for the next event
+            message = socket.recv() => match message {
+                Some(Ok(Message::Ping(payload))) => {
+                    if socket.send(Message::Pong(payload)).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // The stream is one-way, anything else the client sends is ignored
+                Some(Ok(_)) => continue,
+            },
+        };
+        if !filter.matches(&event.event) {
+            continue;
+        }
+        let Ok(message) = serde_json::to_string(&event) else {
+            continue;
+        };
+        // Client went away
+        if socket.send(Message::Text(message)).await.is_err() {
+            break;
+        }
+    }
+    println!("WebSocket event stream closed");
 }
 
 // Create router
@@ -145,6 +909,27 @@ pub fn create_router(wallet: Arc<BitServWallet>) -> Router {
         .route("/new-address", get(get_new_address))
         .route("/addresses", get(get_all_addresses))
         .route("/addresses/balances", get(get_addresses_with_balance))
+        .route("/transactions", get(get_transactions))
+        .route("/transactions/:txid", get(get_transaction))
+        .route("/utxos", get(get_utxos))
+        .route("/utxos/:outpoint/freeze", post(freeze_utxo))
+        .route("/utxos/:outpoint/unfreeze", post(unfreeze_utxo))
+        .route("/withdrawals", post(create_withdrawal))
+        .route("/withdrawals/queue", post(queue_withdrawal))
+        .route("/withdrawals/:id", get(get_withdrawal))
+        .route("/withdrawals/:id/cancel", post(cancel_withdrawal))
+        .route("/customers/:id/address", post(get_customer_address))
+        .route("/customers/:id/balance", get(get_customer_balance))
+        .route("/customers/:id/history", get(get_customer_history))
+        .route(
+            "/customers/by-address/:address",
+            get(get_customer_by_address),
+        )
+        .route("/invoices", post(create_invoice))
+        .route("/invoices/:id", get(get_invoice))
+        .route("/events", get(get_events))
+        .route("/events/stream", get(stream_events))
+        .route("/events/ws", get(stream_events_ws))
         .route("/test/pub-deposits", post(test_pub_deposits))
         .with_state(wallet)
 }
@@ -0,0 +1,78 @@
+use anyhow::{anyhow, Result};
+use std::io::{self, BufRead, Write};
+
+use bitserv::{config, MnemonicStorage, WalletPaths};
+
+const USAGE: &str = "Usage: bitserv-admin <change-password|restore>";
+
+fn main() -> Result<()> {
+    let command = std::env::args().nth(1);
+    match command.as_deref() {
+        Some("change-password") => change_password(),
+        Some("restore") => restore(),
+        _ => Err(anyhow!(USAGE)),
+    }
+}
+
+/// Re-encrypt the configured wallet's mnemonic under a new password.
+/// The passwords are read from stdin so they don't end up in the shell history.
+fn change_password() -> Result<()> {
+    let paths = WalletPaths::new(config::data_dir(), config::wallet_name())?;
+    if !paths.mnemonic_path.exists() {
+        return Err(anyhow!("No wallet found in {}", paths.wallet_dir.display()));
+    }
+
+    let old_password = prompt("Current password: ")?;
+    let new_password = prompt("New password: ")?;
+    if new_password.is_empty() {
+        return Err(anyhow!("The new password must not be empty"));
+    }
+    if prompt("Repeat new password: ")? != new_password {
+        return Err(anyhow!("Passwords don't match"));
+    }
+
+    MnemonicStorage::new(paths.mnemonic_path)
+        .with_kdf(config::kdf_params())
+        .change_password(&old_password, &new_password)?;
+    println!("Password changed. Update WALLET_PW before restarting the server.");
+    Ok(())
+}
+
+/// Create the configured wallet from an existing BIP39 mnemonic
+fn restore() -> Result<()> {
+    let paths = WalletPaths::new(config::data_dir(), config::wallet_name())?;
+    if paths.mnemonic_path.exists() || paths.wallet_path.exists() {
+        return Err(anyhow!(
+            "A wallet already exists in {}",
+            paths.wallet_dir.display()
+        ));
+    }
+
+    let mnemonic = prompt("Mnemonic: ")?;
+    // Leave empty to supply the passphrase at startup through BIP39_PASSPHRASE(_FILE) instead
+    let passphrase = prompt("BIP39 passphrase to store (empty for none): ")?;
+    let password = prompt("Wallet password: ")?;
+    if password.is_empty() {
+        return Err(anyhow!("The password must not be empty"));
+    }
+    if prompt("Repeat wallet password: ")? != password {
+        return Err(anyhow!("Passwords don't match"));
+    }
+
+    MnemonicStorage::new(paths.mnemonic_path)
+        .with_kdf(config::kdf_params())
+        .restore(&mnemonic, Some(&passphrase), &password)?;
+    println!(
+        "Wallet restored to {}. Set WALLET_PW to its password and start the server to rescan.",
+        paths.wallet_dir.display()
+    );
+    Ok(())
+}
+
+fn prompt(message: &str) -> Result<String> {
+    print!("{}", message);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
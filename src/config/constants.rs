@@ -1,7 +1,10 @@
+use bdk_wallet::bitcoin::Network;
 use config::{Config, ConfigError};
 use lazy_static::lazy_static;
 use serde::Deserialize;
-use std::env;
+use std::{env, fs};
+
+use crate::wallet::mnemonic::{Argon2Variant, KdfParams};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Environment {
@@ -28,6 +31,23 @@ impl Environment {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainBackend {
+    Rpc,
+    Electrum,
+    Esplora,
+}
+
+impl ChainBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChainBackend::Rpc => "rpc",
+            ChainBackend::Electrum => "electrum",
+            ChainBackend::Esplora => "esplora",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub environment: String,
@@ -36,7 +56,81 @@ pub struct Settings {
     pub btcd_url: String,
     pub btcd_username: String,
     pub btcd_password: String,
+    pub btcd_zmq_url: Option<String>,
     pub wallet_pw: String,
+    pub bip39_passphrase: Option<String>,
+    pub bip39_passphrase_file: Option<String>, // alternative to BIP39_PASSPHRASE, kept out of the env
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    #[serde(default = "default_wallet_name")]
+    pub wallet_name: String,
+    // Argon2id costs for newly encrypted mnemonic files
+    #[serde(default = "default_argon2_memory")]
+    pub argon2_memory: u32, // in KiB
+    #[serde(default = "default_argon2_iterations")]
+    pub argon2_iterations: u32,
+    #[serde(default = "default_argon2_parallelism")]
+    pub argon2_parallelism: u32,
+    #[serde(default = "default_network")]
+    pub network: String, // mainnet, testnet, testnet4, signet or regtest
+    #[serde(default = "default_chain_backend")]
+    pub chain_backend: String,
+    pub electrum_url: Option<String>,
+    pub esplora_url: Option<String>,
+    pub webhook_urls: Option<String>,    // comma separated
+    pub webhook_secrets: Option<String>, // comma separated, same order as webhook_urls
+    #[serde(default = "default_confirmation_threshold")]
+    pub confirmation_threshold: u32,
+    #[serde(default = "default_withdrawal_batch_interval")]
+    pub withdrawal_batch_interval: u64, // in seconds
+    #[serde(default = "default_withdrawal_fee_rate")]
+    pub withdrawal_fee_rate: u64, // in sat/vB
+    #[serde(default = "default_invoice_expiry")]
+    pub invoice_expiry: u64, // in seconds
+}
+
+fn default_data_dir() -> String {
+    String::from("data")
+}
+
+fn default_wallet_name() -> String {
+    String::from("default")
+}
+
+fn default_argon2_memory() -> u32 {
+    argon2::Params::DEFAULT_M_COST
+}
+
+fn default_argon2_iterations() -> u32 {
+    argon2::Params::DEFAULT_T_COST
+}
+
+fn default_argon2_parallelism() -> u32 {
+    argon2::Params::DEFAULT_P_COST
+}
+
+fn default_network() -> String {
+    String::from("regtest")
+}
+
+fn default_chain_backend() -> String {
+    String::from("rpc")
+}
+
+fn default_confirmation_threshold() -> u32 {
+    6
+}
+
+fn default_withdrawal_batch_interval() -> u64 {
+    600
+}
+
+fn default_withdrawal_fee_rate() -> u64 {
+    2
+}
+
+fn default_invoice_expiry() -> u64 {
+    3600
 }
 
 impl Settings {
@@ -66,6 +160,37 @@ impl Settings {
             _ => Environment::Test,
         }
     }
+
+    pub fn network(&self) -> Network {
+        parse_network(&self.network).unwrap_or_else(|| panic!("Unknown NETWORK {}", self.network))
+    }
+
+    pub fn chain_backend(&self) -> ChainBackend {
+        parse_chain_backend(&self.chain_backend)
+            .unwrap_or_else(|| panic!("Unknown CHAIN_BACKEND {}", self.chain_backend))
+    }
+}
+
+/// Backend for a CHAIN_BACKEND setting, `None` if it names no known backend
+pub fn parse_chain_backend(name: &str) -> Option<ChainBackend> {
+    match name {
+        "rpc" => Some(ChainBackend::Rpc),
+        "electrum" => Some(ChainBackend::Electrum),
+        "esplora" => Some(ChainBackend::Esplora),
+        _ => None,
+    }
+}
+
+/// Network for a NETWORK setting, `None` if it names no known network
+pub fn parse_network(name: &str) -> Option<Network> {
+    match name {
+        "mainnet" | "bitcoin" => Some(Network::Bitcoin),
+        "testnet" => Some(Network::Testnet),
+        "testnet4" => Some(Network::Testnet4),
+        "signet" => Some(Network::Signet),
+        "regtest" => Some(Network::Regtest),
+        _ => None,
+    }
 }
 
 // Create a lazy static instance of Settings
@@ -78,6 +203,14 @@ pub fn environment() -> Environment {
     SETTINGS.environment()
 }
 
+pub fn network() -> Network {
+    SETTINGS.network()
+}
+
+pub fn chain_backend() -> ChainBackend {
+    SETTINGS.chain_backend()
+}
+
 pub fn btcd_url() -> &'static str {
     &SETTINGS.btcd_url
 }
@@ -90,10 +223,70 @@ pub fn btcd_password() -> &'static str {
     &SETTINGS.btcd_password
 }
 
+pub fn electrum_url() -> &'static str {
+    SETTINGS
+        .electrum_url
+        .as_deref()
+        .expect("ELECTRUM_URL must be set to use the electrum backend")
+}
+
+pub fn esplora_url() -> &'static str {
+    SETTINGS
+        .esplora_url
+        .as_deref()
+        .expect("ESPLORA_URL must be set to use the esplora backend")
+}
+
+pub fn btcd_zmq_url() -> Option<&'static str> {
+    SETTINGS.btcd_zmq_url.as_deref()
+}
+
 pub fn wallet_pw() -> &'static str {
     &SETTINGS.wallet_pw
 }
 
+pub fn kdf_params() -> KdfParams {
+    let kdf = KdfParams {
+        variant: Argon2Variant::Argon2id,
+        memory: SETTINGS.argon2_memory,
+        iterations: SETTINGS.argon2_iterations,
+        parallelism: SETTINGS.argon2_parallelism,
+    };
+    if let Err(e) = kdf.check_minimum() {
+        panic!("Invalid ARGON2 settings: {}", e);
+    }
+    kdf
+}
+
+/// BIP39 passphrase supplied at startup instead of being stored with the mnemonic
+pub fn bip39_passphrase() -> Option<String> {
+    let set = |value: &'static Option<String>| value.as_deref().filter(|value| !value.is_empty());
+    let passphrase = match (
+        set(&SETTINGS.bip39_passphrase),
+        set(&SETTINGS.bip39_passphrase_file),
+    ) {
+        (Some(_), Some(_)) => {
+            panic!("Set either BIP39_PASSPHRASE or BIP39_PASSPHRASE_FILE, not both")
+        }
+        (Some(passphrase), None) => Some(passphrase.to_string()),
+        (None, Some(path)) => {
+            let passphrase = fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("Failed to read BIP39_PASSPHRASE_FILE {}: {}", path, e));
+            Some(passphrase.trim_end_matches(['\r', '\n']).to_string())
+        }
+        (None, None) => None,
+    };
+    passphrase.filter(|passphrase| !passphrase.is_empty())
+}
+
+pub fn data_dir() -> &'static str {
+    &SETTINGS.data_dir
+}
+
+pub fn wallet_name() -> &'static str {
+    &SETTINGS.wallet_name
+}
+
 pub fn port() -> u16 {
     SETTINGS.port
 }
@@ -101,3 +294,40 @@ pub fn port() -> u16 {
 pub fn publisher_bind_address() -> &'static str {
     &SETTINGS.publisher_bind_address
 }
+
+/// Configured webhook endpoints as (url, secret) pairs
+pub fn webhook_endpoints() -> Vec<(String, String)> {
+    let split = |value: &Option<String>| -> Vec<String> {
+        value
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect()
+    };
+    let urls = split(&SETTINGS.webhook_urls);
+    let secrets = split(&SETTINGS.webhook_secrets);
+    assert_eq!(
+        urls.len(),
+        secrets.len(),
+        "WEBHOOK_URLS and WEBHOOK_SECRETS must have the same number of entries"
+    );
+    urls.into_iter().zip(secrets).collect()
+}
+
+pub fn confirmation_threshold() -> u32 {
+    SETTINGS.confirmation_threshold
+}
+
+pub fn withdrawal_batch_interval() -> u64 {
+    SETTINGS.withdrawal_batch_interval
+}
+
+pub fn withdrawal_fee_rate() -> u64 {
+    SETTINGS.withdrawal_fee_rate
+}
+
+pub fn invoice_expiry() -> u64 {
+    SETTINGS.invoice_expiry
+}
@@ -4,6 +4,20 @@ pub mod pubsub;
 mod wallet;
 
 // Re-export the main types that users of our library will need
-pub use pubsub::Publisher;
+pub use pubsub::{EventBus, Publisher};
 pub use wallet::bitserv::BitServWallet;
 pub use wallet::client::Client;
+pub use wallet::coins::{FrozenCoins, FrozenOutpoint, Utxo};
+pub use wallet::customers::{CustomerAddress, CustomerRegistry};
+pub use wallet::deposits::{DepositTracker, TrackedDeposit};
+pub use wallet::invoices::{Invoice, InvoiceBook, InvoiceStatus};
+pub use wallet::ledger::{Ledger, LedgerEntry};
+pub use wallet::mnemonic::{Argon2Variant, KdfParams, MnemonicStorage, Seed};
+pub use wallet::notifier::BlockNotifier;
+pub use wallet::paths::{WalletPaths, LEGACY_DIR};
+pub use wallet::sync::SyncWorker;
+pub use wallet::transactions::{
+    Derivation, Direction, InputDetail, OutputDetail, TransactionDetail, TransactionFilter,
+    TransactionSummary,
+};
+pub use wallet::withdrawals::{Withdrawal, WithdrawalQueue, WithdrawalStatus};
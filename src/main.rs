@@ -1,10 +1,14 @@
 use anyhow::Result;
 use bdk_bitcoind_rpc::bitcoincore_rpc::Auth;
-use bdk_wallet::bitcoin::Network;
 use log::info;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use bitserv::{api::create_router, config, BitServWallet, Client};
+use bitserv::{
+    api::create_router,
+    config::{self, ChainBackend},
+    pubsub::{WebhookEndpoint, ZmqPublisher},
+    BitServWallet, Client, WalletPaths, LEGACY_DIR,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -13,22 +17,59 @@ async fn main() -> Result<()> {
     info!("Starting BitServ wallet application...");
 
     let port = config::port();
-    let bitcoind_url = config::btcd_url();
-    let bitcoind_username = config::btcd_username();
-    let bitcoind_password = config::btcd_password();
     let password = config::wallet_pw();
-    let network = Network::Regtest;
+    let network = config::network();
+    let passphrase = config::bip39_passphrase();
+    let kdf = config::kdf_params();
+    let webhook_endpoints = config::webhook_endpoints()
+        .into_iter()
+        .map(|(url, secret)| WebhookEndpoint { url, secret })
+        .collect();
 
-    let auth = Auth::UserPass(bitcoind_username.to_string(), bitcoind_password.to_string());
-    let client = Client::new_rpc(bitcoind_url, auth);
+    info!("Using {} network", network);
+    info!("Using {} chain backend", config::chain_backend().as_str());
+    let client = match config::chain_backend() {
+        ChainBackend::Rpc => {
+            let bitcoind_url = config::btcd_url();
+            let bitcoind_username = config::btcd_username();
+            let bitcoind_password = config::btcd_password();
+            let auth = Auth::UserPass(bitcoind_username.to_string(), bitcoind_password.to_string());
+            Client::new_rpc(bitcoind_url, auth)
+        }
+        ChainBackend::Electrum => Client::new_electrum(config::electrum_url()),
+        ChainBackend::Esplora => Client::new_esplora(config::esplora_url()),
+    };
 
-    let mut wallet = BitServWallet::new(password, network);
+    let paths = WalletPaths::new(config::data_dir(), config::wallet_name())?;
+    paths.migrate_legacy(LEGACY_DIR, password)?;
+    info!("Using wallet directory {}", paths.wallet_dir.display());
+
+    let publisher = ZmqPublisher::new(config::publisher_bind_address())?;
+    let mut wallet = BitServWallet::new(
+        &paths,
+        password,
+        passphrase,
+        kdf,
+        network,
+        config::confirmation_threshold(),
+        Box::new(publisher),
+        webhook_endpoints,
+    );
     wallet.init(&client);
+    if let Some(zmq_url) = config::btcd_zmq_url() {
+        wallet.set_zmq_endpoint(zmq_url);
+    }
     wallet.sync(client);
 
     // Create Arc for sharing wallet between threads
     let wallet = Arc::new(wallet);
 
+    // Pay out queued withdrawals in batches
+    wallet.start_withdrawal_scheduler(
+        Duration::from_secs(config::withdrawal_batch_interval()),
+        config::withdrawal_fee_rate(),
+    );
+
     // Create router
     let app = create_router(wallet);
 
@@ -1,9 +1,23 @@
+mod outbox;
+mod publisher;
+mod webhook;
+
 use anyhow::Result;
+use bdk_wallet::KeychainKind;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use zmq::{Context, Socket};
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+
+use crate::{InvoiceStatus, WithdrawalStatus};
+
+pub use outbox::{Outbox, SequencedEvent};
+pub use publisher::{MemoryPublisher, NoopPublisher, Publisher, ZmqPublisher};
+pub use webhook::{
+    sign, RetryPolicy, WebhookDelivery, WebhookDispatcher, WebhookEndpoint, SEQUENCE_HEADER,
+    SIGNATURE_HEADER,
+};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ChainEvent {
     #[serde(rename = "newtx")]
     NewTransaction {
@@ -11,39 +25,166 @@ pub enum ChainEvent {
         amount: i64,
         confirmations: u32,
     },
+    /// Address handed out by the wallet, with where it was derived from
     NewAddress {
         address: String,
+        index: u32,
+        keychain: KeychainKind,
     },
     #[serde(rename = "dpsts")]
     NewDeposits {
         deposits: Vec<(String, u64, String)>, // (address, amount, txid)
+        #[serde(default)]
+        customers: BTreeMap<String, String>, // address -> customer id
+    },
+    /// Deposits seen in the mempool that haven't been mined yet
+    #[serde(rename = "pdpsts")]
+    PendingDeposits {
+        deposits: Vec<(String, u64, String)>, // (address, amount, txid)
+    },
+    /// Deposits that reached the confirmation threshold
+    #[serde(rename = "cdpsts")]
+    CreditedDeposits {
+        deposits: Vec<(String, u64, String)>, // (address, amount, txid)
+    },
+    /// Previously published deposits whose block was disconnected by a reorg
+    #[serde(rename = "rdpsts")]
+    RevertedDeposits {
+        deposits: Vec<(String, u64, String)>, // (address, amount, txid)
+    },
+    #[serde(rename = "wthdrw")]
+    Withdrawal {
+        id: i64,
+        address: String,
+        amount: u64,
+        txid: String,
+        status: WithdrawalStatus,
+    },
+    /// Payment progress of an invoice changed
+    #[serde(rename = "invc")]
+    Invoice {
+        id: i64,
+        address: String,
+        amount: u64,
+        received: u64,
+        #[serde(default)]
+        confirmed: u64,
+        status: InvoiceStatus,
     },
 }
 
-pub struct Publisher {
-    socket: Socket,
+impl ChainEvent {
+    /// Event type as it appears in the serialized event
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChainEvent::NewTransaction { .. } => "newtx",
+            ChainEvent::NewAddress { .. } => "NewAddress",
+            ChainEvent::NewDeposits { .. } => "dpsts",
+            ChainEvent::PendingDeposits { .. } => "pdpsts",
+            ChainEvent::CreditedDeposits { .. } => "cdpsts",
+            ChainEvent::RevertedDeposits { .. } => "rdpsts",
+            ChainEvent::Withdrawal { .. } => "wthdrw",
+            ChainEvent::Invoice { .. } => "invc",
+        }
+    }
+
+    /// ZMQ topic the event is sent under. Subscriptions match by prefix, so
+    /// subscribing to `deposits` receives every deposit event.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            ChainEvent::NewTransaction { .. } => "tx",
+            ChainEvent::NewAddress { .. } => "address",
+            ChainEvent::NewDeposits { .. } => "deposits.new",
+            ChainEvent::PendingDeposits { .. } => "deposits.pending",
+            ChainEvent::CreditedDeposits { .. } => "deposits.credited",
+            ChainEvent::RevertedDeposits { .. } => "deposits.reverted",
+            ChainEvent::Withdrawal { .. } => "withdrawal",
+            ChainEvent::Invoice { .. } => "invoice",
+        }
+    }
+
+    /// Addresses the event is about
+    pub fn addresses(&self) -> Vec<&str> {
+        match self {
+            ChainEvent::NewTransaction { .. } => Vec::new(),
+            ChainEvent::NewAddress { address, .. }
+            | ChainEvent::Withdrawal { address, .. }
+            | ChainEvent::Invoice { address, .. } => {
+                vec![address.as_str()]
+            }
+            ChainEvent::NewDeposits { deposits, .. }
+            | ChainEvent::PendingDeposits { deposits }
+            | ChainEvent::CreditedDeposits { deposits }
+            | ChainEvent::RevertedDeposits { deposits } => deposits
+                .iter()
+                .map(|(address, _, _)| address.as_str())
+                .collect(),
+        }
+    }
+}
+
+/// Selects which events a stream subscriber receives. Empty filters match everything.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub kinds: Vec<String>,
+    pub address: Option<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &ChainEvent) -> bool {
+        let kind_matches = self.kinds.is_empty() || self.kinds.iter().any(|k| k == event.kind());
+        let address_matches = match &self.address {
+            Some(address) => event.addresses().contains(&address.as_str()),
+            None => true,
+        };
+        kind_matches && address_matches
+    }
+}
+
+/// Number of events buffered for slow stream subscribers before they start lagging
+const STREAM_CAPACITY: usize = 1024;
+
+/// Stores every event in the outbox, then hands it to the publisher, the
+/// webhooks and the HTTP stream subscribers
+pub struct EventBus {
+    publisher: Box<dyn Publisher>,
+    outbox: Outbox,
+    webhooks: Option<WebhookDispatcher>,
+    stream: broadcast::Sender<SequencedEvent>,
 }
 
-impl Publisher {
-    pub fn new(bind_address: &str) -> Result<Self> {
-        let context = Context::new();
-        let socket = context.socket(zmq::PUB)?;
-        socket.bind(bind_address)?;
-        Ok(Self { socket })
+impl EventBus {
+    pub fn new(publisher: Box<dyn Publisher>, outbox: Outbox) -> Self {
+        let (stream, _) = broadcast::channel(STREAM_CAPACITY);
+        Self {
+            publisher,
+            outbox,
+            webhooks: None,
+            stream,
+        }
+    }
+
+    /// Receive every event published from now on, e.g. to stream it over HTTP
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.stream.subscribe()
     }
 
+    /// Also deliver every published event to HTTP webhooks
+    pub fn set_webhooks(&mut self, webhooks: WebhookDispatcher) {
+        self.webhooks = Some(webhooks);
+    }
+
+    /// Store the event in the outbox, then send it tagged with its sequence number.
+    /// Events that fail to send can still be fetched from the outbox.
     pub fn publish(&self, event: ChainEvent) -> Result<()> {
-        let message = serde_json::to_string(&event)?;
-        // Send topic first
-        self.socket.send("tx", zmq::SNDMORE)?;
-        // Then send the actual message
-        self.socket.send(&message, 0)?;
+        let seq = self.outbox.append(&event)?;
+        let event = SequencedEvent { seq, event };
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.dispatch(&event)?;
+        }
+        self.publisher.publish(&event)?;
+        // Only fails when nobody is subscribed
+        let _ = self.stream.send(event);
         Ok(())
     }
 }
-
-// Helper function to create a publisher instance
-pub fn create_publisher() -> Arc<Publisher> {
-    let publisher = Publisher::new("tcp://*:5556").expect("Failed to create ZMQ publisher");
-    Arc::new(publisher)
-}
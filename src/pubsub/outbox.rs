@@ -0,0 +1,93 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use bdk_wallet::rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::ChainEvent;
+
+/// A published event together with its position in the outbox
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    pub event: ChainEvent,
+}
+
+/// Every published event, stored in the wallet's SQLite database so consumers
+/// can catch up on what they missed while they were down.
+#[derive(Clone)]
+pub struct Outbox {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Outbox {
+    pub fn new(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+        // AUTOINCREMENT keeps sequence numbers strictly increasing, even after deletes
+        conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS bitserv_outbox (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                event TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Persist an event, returning its sequence number
+    pub fn append(&self, event: &ChainEvent) -> Result<u64> {
+        let json = serde_json::to_string(event)?;
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO bitserv_outbox (event, created_at) VALUES (?1, ?2)",
+            params![json, created_at],
+        )?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    pub fn get(&self, seq: u64) -> Result<Option<SequencedEvent>> {
+        let json: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT event FROM bitserv_outbox WHERE seq = ?1",
+                params![seq],
+                |row| row.get(0),
+            )
+            .optional()?;
+        json.map(|json| {
+            Ok(SequencedEvent {
+                seq,
+                event: serde_json::from_str(&json)?,
+            })
+        })
+        .transpose()
+    }
+
+    /// Events with a sequence number greater than `seq`, oldest first
+    pub fn since(&self, seq: u64, limit: u32) -> Result<Vec<SequencedEvent>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT seq, event FROM bitserv_outbox WHERE seq > ?1 ORDER BY seq LIMIT ?2",
+        )?;
+        let rows = stmt
+            .query_map(params![seq, limit], |row| {
+                Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(seq, json)| {
+                Ok(SequencedEvent {
+                    seq,
+                    event: serde_json::from_str(&json)?,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use zmq::{Context, Socket};
+
+use super::SequencedEvent;
+
+/// Transport that pushes published events out to subscribers
+pub trait Publisher: Send {
+    fn publish(&self, event: &SequencedEvent) -> Result<()>;
+}
+
+/// Sends every event as a two-part ZMQ message: the event's topic, then the JSON event
+pub struct ZmqPublisher {
+    socket: Socket,
+}
+
+impl ZmqPublisher {
+    pub fn new(bind_address: &str) -> Result<Self> {
+        let context = Context::new();
+        let socket = context.socket(zmq::PUB)?;
+        socket.bind(bind_address)?;
+        Ok(Self { socket })
+    }
+}
+
+impl Publisher for ZmqPublisher {
+    fn publish(&self, event: &SequencedEvent) -> Result<()> {
+        let message = serde_json::to_string(event)?;
+        // Send topic first
+        self.socket.send(event.event.topic(), zmq::SNDMORE)?;
+        // Then send the actual message
+        self.socket.send(&message, 0)?;
+        Ok(())
+    }
+}
+
+/// Keeps published events in memory, clones share the same events
+#[derive(Clone, Default)]
+pub struct MemoryPublisher {
+    events: Arc<Mutex<Vec<SequencedEvent>>>,
+}
+
+impl MemoryPublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<SequencedEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl Publisher for MemoryPublisher {
+    fn publish(&self, event: &SequencedEvent) -> Result<()> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+/// Drops every event, they're still stored in the outbox
+pub struct NoopPublisher;
+
+impl Publisher for NoopPublisher {
+    fn publish(&self, _event: &SequencedEvent) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread::sleep,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use bdk_wallet::rusqlite::{params, Connection};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use super::{Outbox, SequencedEvent};
+
+/// Header carrying the hex HMAC-SHA256 of the request body, prefixed with `sha256=`
+pub const SIGNATURE_HEADER: &str = "X-Bitserv-Signature";
+/// Header carrying the outbox sequence number of the event
+pub const SEQUENCE_HEADER: &str = "X-Bitserv-Seq";
+
+#[derive(Debug, Clone)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            initial_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    pub seq: u64,
+    pub url: String,
+    pub status: String, // pending, delivered or failed
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+/// POSTs every published event as JSON to the configured endpoints. Each endpoint
+/// gets its own delivery thread so a slow or failing receiver doesn't hold up the others.
+/// Deliveries still pending when the process stopped are queued again on startup.
+pub struct WebhookDispatcher {
+    conn: Arc<Mutex<Connection>>,
+    senders: Vec<(String, Sender<(u64, String)>)>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(
+        conn: Arc<Mutex<Connection>>,
+        endpoints: Vec<WebhookEndpoint>,
+        retry: RetryPolicy,
+    ) -> Result<Self> {
+        conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS bitserv_webhook_deliveries (
+                seq INTEGER NOT NULL,
+                url TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                last_error TEXT,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (seq, url)
+            )",
+            [],
+        )?;
+
+        let outbox = Outbox::new(conn.clone())?;
+        let mut senders = Vec::new();
+        for endpoint in endpoints {
+            let (tx, rx) = mpsc::channel::<(u64, String)>();
+            let url = endpoint.url.clone();
+            let thread_conn = conn.clone();
+            let _ = std::thread::spawn(move || {
+                // Built on the delivery thread, since the blocking client panics when
+                // created or dropped inside the async runtime the server runs in
+                let client = match reqwest::blocking::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                {
+                    Ok(client) => client,
+                    Err(e) => {
+                        eprintln!(
+                            "Failed to create webhook client for {}: {}",
+                            endpoint.url, e
+                        );
+                        return;
+                    }
+                };
+                for (seq, body) in rx {
+                    deliver(&thread_conn, &client, &endpoint, retry, seq, &body);
+                }
+            });
+            requeue_pending(&conn, &outbox, &url, &tx)?;
+            senders.push((url, tx));
+        }
+
+        Ok(Self { conn, senders })
+    }
+
+    /// Queue an event for delivery to every endpoint
+    pub fn dispatch(&self, event: &SequencedEvent) -> Result<()> {
+        let body = serde_json::to_string(event)?;
+        for (url, sender) in &self.senders {
+            // Record the delivery before queueing it, so it's retried after a restart
+            record_delivery(&self.conn, event.seq, url, "pending", 0, None)?;
+            sender
+                .send((event.seq, body.clone()))
+                .map_err(|_| anyhow!("Webhook delivery thread stopped"))?;
+        }
+        Ok(())
+    }
+
+    pub fn deliveries(&self, seq: u64) -> Result<Vec<WebhookDelivery>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT seq, url, status, attempts, last_error FROM bitserv_webhook_deliveries
+             WHERE seq = ?1 ORDER BY url",
+        )?;
+        let deliveries = stmt
+            .query_map(params![seq], |row| {
+                Ok(WebhookDelivery {
+                    seq: row.get(0)?,
+                    url: row.get(1)?,
+                    status: row.get(2)?,
+                    attempts: row.get(3)?,
+                    last_error: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(deliveries)
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `body` keyed with `secret`
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn deliver(
+    conn: &Mutex<Connection>,
+    client: &reqwest::blocking::Client,
+    endpoint: &WebhookEndpoint,
+    retry: RetryPolicy,
+    seq: u64,
+    body: &str,
+) {
+    let signature = format!("sha256={}", sign(&endpoint.secret, body));
+    let mut backoff = retry.initial_backoff;
+
+    for attempt in 1..=retry.max_attempts {
+        let result = client
+            .post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .header(SEQUENCE_HEADER, seq.to_string())
+            .body(body.to_string())
+            .send()
+            .map_err(|e| e.to_string())
+            .and_then(|response| {
+                if response.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(format!("Receiver responded with {}", response.status()))
+                }
+            });
+
+        let status = match &result {
+            Ok(()) => "delivered",
+            Err(_) if attempt == retry.max_attempts => "failed",
+            Err(_) => "pending",
+        };
+        let last_error = result.as_ref().err().cloned();
+        if let Err(e) = record_delivery(conn, seq, &endpoint.url, status, attempt, last_error) {
+            eprintln!("Failed to record webhook delivery {}: {}", seq, e);
+        }
+
+        match result {
+            Ok(()) => return,
+            Err(e) => {
+                eprintln!(
+                    "Webhook delivery of event {} to {} failed (attempt {}): {}",
+                    seq, endpoint.url, attempt, e
+                );
+                if attempt < retry.max_attempts {
+                    sleep(backoff);
+                    backoff *= 2;
+                }
+            }
+        }
+    }
+}
+
+/// Queue the events whose delivery to `url` was still pending, oldest first.
+/// They get a fresh round of attempts.
+fn requeue_pending(
+    conn: &Mutex<Connection>,
+    outbox: &Outbox,
+    url: &str,
+    sender: &Sender<(u64, String)>,
+) -> Result<()> {
+    let seqs = {
+        let conn = conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT seq FROM bitserv_webhook_deliveries
+             WHERE url = ?1 AND status = 'pending' ORDER BY seq",
+        )?;
+        let seqs = stmt
+            .query_map(params![url], |row| row.get::<_, u64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        seqs
+    };
+
+    for seq in &seqs {
+        let Some(event) = outbox.get(*seq)? else {
+            eprintln!("Pending webhook event {} is missing from the outbox", seq);
+            continue;
+        };
+        sender
+            .send((*seq, serde_json::to_string(&event)?))
+            .map_err(|_| anyhow!("Webhook delivery thread stopped"))?;
+    }
+    if !seqs.is_empty() {
+        println!(
+            "Re-queued {} pending webhook deliveries to {}",
+            seqs.len(),
+            url
+        );
+    }
+    Ok(())
+}
+
+fn record_delivery(
+    conn: &Mutex<Connection>,
+    seq: u64,
+    url: &str,
+    status: &str,
+    attempts: u32,
+    last_error: Option<String>,
+) -> Result<()> {
+    let updated_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    conn.lock().unwrap().execute(
+        "INSERT INTO bitserv_webhook_deliveries (seq, url, status, attempts, last_error, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(seq, url) DO UPDATE SET status = excluded.status,
+            attempts = excluded.attempts, last_error = excluded.last_error,
+            updated_at = excluded.updated_at",
+        params![seq, url, status, attempts, last_error, updated_at],
+    )?;
+    Ok(())
+}
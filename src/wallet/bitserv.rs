@@ -1,26 +1,50 @@
 use std::{
-    hash::Hash,
+    collections::BTreeMap,
+    str::FromStr,
     sync::{mpsc::Sender, Arc, Mutex},
     thread::sleep,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::Result;
-use bdk_bitcoind_rpc::{bitcoincore_rpc::RpcApi, Emitter};
+use anyhow::{anyhow, Result};
+use bdk_bitcoind_rpc::{
+    bitcoincore_rpc::{self, RpcApi},
+    Emitter,
+};
+use bdk_electrum::electrum_client::{self, ElectrumApi};
+use bdk_esplora::{esplora_client, EsploraExt};
 use bdk_wallet::{
     bip39::{Language, Mnemonic},
-    bitcoin::{self, Network, Transaction},
+    bitcoin::{
+        self, constants::genesis_block, Address, Amount, BlockHash, FeeRate, Network, OutPoint,
+        ScriptBuf, Transaction, Txid,
+    },
     chain::CheckPoint,
+    error::CreateTxError,
     keys::{DerivableKey, ExtendedKey},
     rusqlite::Connection,
     template::Bip84,
-    Balance, KeychainKind, PersistedWallet, Wallet,
+    AddressInfo, Balance, KeychainKind, PersistedWallet, SignOptions, Wallet,
 };
-
-use super::{client::Client, mnemonic::MnemonicStorage, paths::WalletPaths};
-use crate::{
-    config,
-    pubsub::{ChainEvent, Publisher},
+use tokio::sync::broadcast;
+
+use super::{
+    client::Client,
+    coins::{self, FrozenCoins, Utxo},
+    customers::{CustomerAddress, CustomerRegistry},
+    deposits::DepositTracker,
+    invoices::{Invoice, InvoiceBook},
+    ledger::{Ledger, LedgerEntry},
+    mnemonic::{KdfParams, MnemonicStorage},
+    notifier::BlockNotifier,
+    paths::WalletPaths,
+    sync::{SyncWorker, BATCH_SIZE, PARALLEL_REQUESTS, STOP_GAP},
+    transactions::{self, TransactionDetail, TransactionFilter, TransactionSummary},
+    withdrawals::{Withdrawal, WithdrawalQueue, WithdrawalStatus},
+};
+use crate::pubsub::{
+    ChainEvent, EventBus, Outbox, Publisher, RetryPolicy, SequencedEvent, WebhookDispatcher,
+    WebhookEndpoint,
 };
 
 pub struct BitServWallet {
@@ -28,29 +52,66 @@ pub struct BitServWallet {
     conn: Arc<Mutex<Connection>>,
     is_syncing: bool,
     stop_sync_tx: Option<Sender<()>>,
-    publisher: Arc<Mutex<Publisher>>,
+    publisher: Arc<Mutex<EventBus>>,
+    client: Option<Arc<Client>>,
+    withdrawals: WithdrawalQueue,
+    zmq_endpoint: Option<String>,
+    deposits: DepositTracker,
+    confirmation_threshold: u32,
+    outbox: Outbox,
+    customers: CustomerRegistry,
+    // Serializes address assignment so a customer never gets two "stable" addresses
+    customer_assignment: Mutex<()>,
+    ledger: Ledger,
+    invoices: InvoiceBook,
+    frozen: FrozenCoins,
 }
 
+/// How long the sync thread waits between polls of the backend
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
 impl BitServWallet {
     pub fn balance(&self) -> Balance {
         let wallet = self.bdk_wallet.lock().unwrap();
         wallet.balance()
     }
 
-    pub fn new(password: &str, network: Network) -> Self {
-        let paths = WalletPaths::from_password(password);
-        let mnemonic_storage = MnemonicStorage::new(paths.mnemonic_path);
-
-        let mnemonic = mnemonic_storage.load_or_create_by_password(password);
-
-        let xkey: ExtendedKey = Mnemonic::parse_in(Language::English, mnemonic)
-            .unwrap()
-            .into_extended_key()
-            .unwrap();
-
-        let mut conn = Connection::open(paths.wallet_path).unwrap();
+    /// Open the wallet stored at `paths`, sending published events out through `publisher`
+    /// and POSTing them to `webhook_endpoints`.
+    /// `passphrase` is a BIP39 passphrase supplied at startup rather than stored with the
+    /// mnemonic, and `kdf` the key derivation costs a new or outdated mnemonic file gets.
+    /// Deposits are credited once they have `confirmation_threshold` confirmations.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        paths: &WalletPaths,
+        password: &str,
+        passphrase: Option<String>,
+        kdf: KdfParams,
+        network: Network,
+        confirmation_threshold: u32,
+        publisher: Box<dyn Publisher>,
+        webhook_endpoints: Vec<WebhookEndpoint>,
+    ) -> Self {
+        let mnemonic_storage = MnemonicStorage::new(paths.mnemonic_path.clone()).with_kdf(kdf);
+
+        // A wrong password must not silently create a new wallet
+        let seed = mnemonic_storage
+            .load_or_create_by_password(password)
+            .and_then(|seed| seed.with_passphrase(passphrase))
+            .unwrap_or_else(|e| {
+                panic!(
+                    "Failed to unlock the wallet in {}: {}",
+                    paths.wallet_dir.display(),
+                    e
+                )
+            });
+
+        let mnemonic = Mnemonic::parse_in(Language::English, seed.mnemonic).unwrap();
+        let xkey: ExtendedKey = (mnemonic, seed.passphrase).into_extended_key().unwrap();
+
+        let mut conn = Connection::open(&paths.wallet_path).unwrap();
         let xprv = xkey.into_xprv(network).unwrap();
-        let bdk_wallet = Wallet::load()
+        let loaded_wallet = Wallet::load()
             .descriptor(
                 KeychainKind::External,
                 Some(Bip84(xprv, KeychainKind::External)),
@@ -62,19 +123,54 @@ impl BitServWallet {
             .extract_keys()
             .check_network(network)
             .load_wallet(&mut conn)
-            .unwrap()
             .unwrap();
-
-        let publisher_bind_address = config::publisher_bind_address();
-
-        let publisher = Arc::new(Mutex::new(Publisher::new(publisher_bind_address).unwrap()));
+        let bdk_wallet = match loaded_wallet {
+            Some(wallet) => wallet,
+            None => Wallet::create(
+                Bip84(xprv, KeychainKind::External),
+                Bip84(xprv, KeychainKind::Internal),
+            )
+            .network(network)
+            .create_wallet(&mut conn)
+            .unwrap(),
+        };
+
+        let conn = Arc::new(Mutex::new(conn));
+        let withdrawals = WithdrawalQueue::new(conn.clone()).unwrap();
+        let deposits = DepositTracker::new(conn.clone()).unwrap();
+        let outbox = Outbox::new(conn.clone()).unwrap();
+        let customers = CustomerRegistry::new(conn.clone()).unwrap();
+        let ledger = Ledger::new(conn.clone()).unwrap();
+        let invoices = InvoiceBook::new(conn.clone()).unwrap();
+        let frozen = FrozenCoins::new(conn.clone()).unwrap();
+
+        let mut publisher = EventBus::new(publisher, outbox.clone());
+
+        if !webhook_endpoints.is_empty() {
+            let webhooks =
+                WebhookDispatcher::new(conn.clone(), webhook_endpoints, RetryPolicy::default())
+                    .unwrap();
+            publisher.set_webhooks(webhooks);
+        }
+        let publisher = Arc::new(Mutex::new(publisher));
 
         Self {
             bdk_wallet: Arc::new(Mutex::new(bdk_wallet)),
-            conn: Arc::new(Mutex::new(conn)),
+            conn,
             is_syncing: false,
             stop_sync_tx: None,
             publisher,
+            client: None,
+            withdrawals,
+            zmq_endpoint: None,
+            deposits,
+            confirmation_threshold: confirmation_threshold.max(1),
+            outbox,
+            customers,
+            customer_assignment: Mutex::new(()),
+            ledger,
+            invoices,
+            frozen,
         }
     }
 
@@ -89,6 +185,13 @@ impl BitServWallet {
                     "\nConnected to Bitcoin Core RPC.\nChain: {}\nLatest block: {} at height {}\n",
                     blockchain_info.chain, blockchain_info.best_block_hash, blockchain_info.blocks,
                 );
+                let network = self.bdk_wallet.lock().unwrap().network();
+                if blockchain_info.chain != network {
+                    panic!(
+                        "bitcoind is running on {} but the wallet is configured for {}",
+                        blockchain_info.chain, network
+                    );
+                }
 
                 let mut wallet_lock = self.bdk_wallet.lock().unwrap();
 
@@ -113,14 +216,85 @@ impl BitServWallet {
                         .unwrap();
                 }
 
+                wallet_lock.persist(&mut self.conn.lock().unwrap()).unwrap();
+                drop(wallet_lock);
+
                 let mempool_emissions: Vec<(Transaction, u64)> = emitter.mempool().unwrap();
-                wallet_lock.apply_unconfirmed_txs(mempool_emissions);
+                self.sync_worker().apply_mempool(mempool_emissions).unwrap();
+                let balance: Balance = self.balance();
+                println!("Wallet balance after syncing: {}", balance.total());
+            }
+            Client::Electrum(electrum_client) => {
+                let header = match electrum_client.inner.block_headers_subscribe() {
+                    Ok(header) => header,
+                    Err(e) => panic!("Error getting latest block header: {}", e),
+                };
+                println!(
+                    "\nConnected to Electrum server.\nLatest block: {} at height {}\n",
+                    header.header.block_hash(),
+                    header.height,
+                );
+                match electrum_client.inner.block_header(0) {
+                    Ok(genesis) => self.check_genesis(genesis.block_hash()),
+                    Err(e) => panic!("Error getting genesis block header: {}", e),
+                }
+
+                let mut wallet_lock = self.bdk_wallet.lock().unwrap();
+
+                // Seed the client cache so known transactions aren't downloaded again
+                electrum_client.populate_tx_cache(
+                    wallet_lock
+                        .tx_graph()
+                        .full_txs()
+                        .map(|tx_node| tx_node.tx.clone()),
+                );
+
+                println!("Full scanning wallet...");
+                let request = wallet_lock
+                    .start_full_scan()
+                    .inspect(|keychain, index, _| {
+                        println!("Scanning {:?} script at index {}", keychain, index);
+                    })
+                    .build();
+                let update = electrum_client
+                    .full_scan(request, STOP_GAP, BATCH_SIZE, true)
+                    .unwrap();
+                wallet_lock.apply_update(update).unwrap();
                 wallet_lock.persist(&mut self.conn.lock().unwrap()).unwrap();
                 let balance: Balance = wallet_lock.balance();
                 println!("Wallet balance after syncing: {}", balance.total());
             }
-            Client::Electrum(_electrum) => {
-                println!("Not supported");
+            Client::Esplora(esplora_client) => {
+                let height = match esplora_client.get_height() {
+                    Ok(height) => height,
+                    Err(e) => panic!("Error getting chain height: {}", e),
+                };
+                println!(
+                    "\nConnected to Esplora API.\nLatest block: {} at height {}\n",
+                    esplora_client.get_tip_hash().unwrap(),
+                    height,
+                );
+                match esplora_client.get_block_hash(0) {
+                    Ok(genesis_hash) => self.check_genesis(genesis_hash),
+                    Err(e) => panic!("Error getting genesis block hash: {}", e),
+                }
+
+                let mut wallet_lock = self.bdk_wallet.lock().unwrap();
+
+                println!("Full scanning wallet...");
+                let request = wallet_lock
+                    .start_full_scan()
+                    .inspect(|keychain, index, _| {
+                        println!("Scanning {:?} script at index {}", keychain, index);
+                    })
+                    .build();
+                let update = esplora_client
+                    .full_scan(request, STOP_GAP, PARALLEL_REQUESTS)
+                    .unwrap();
+                wallet_lock.apply_update(update).unwrap();
+                wallet_lock.persist(&mut self.conn.lock().unwrap()).unwrap();
+                let balance: Balance = wallet_lock.balance();
+                println!("Wallet balance after syncing: {}", balance.total());
             }
         }
     }
@@ -128,84 +302,106 @@ impl BitServWallet {
     pub fn sync(&mut self, client: Client) {
         // Perform a regular sync
         println!("Syncing blocks...");
-        match client {
-            Client::Rpc(rpc_client) => {
-                let wallet_ref = self.bdk_wallet.clone();
-                let conn_ref = self.conn.clone();
-                let publisher = self.publisher.clone();
-                let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let client = Arc::new(client);
+        // Keep a handle around so withdrawals can be broadcast through the same backend
+        self.client = Some(client.clone());
 
-                self.stop_sync_tx = Some(tx);
+        let worker = self.sync_worker();
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
 
-                let _ = std::thread::spawn(move || loop {
-                    let wallet_tip: CheckPoint = wallet_ref.lock().unwrap().latest_checkpoint();
+        self.stop_sync_tx = Some(tx);
 
-                    let mut emitter =
-                        Emitter::new(&rpc_client, wallet_tip.clone(), wallet_tip.height());
-                    while let Some(block_event) = emitter.next_block().unwrap() {
-                        print!("Processing block {} ", block_event.block_height());
+        // Push notifications only make sense for a bitcoind backend
+        let notifier = match (&self.zmq_endpoint, client.as_ref()) {
+            (Some(endpoint), Client::Rpc(_)) => match BlockNotifier::new(endpoint) {
+                Ok(notifier) => {
+                    println!("Listening for bitcoind notifications on {}", endpoint);
+                    Some(notifier)
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to subscribe to {}, polling instead: {}",
+                        endpoint, e
+                    );
+                    None
+                }
+            },
+            (Some(_), _) => {
+                println!("ZMQ notifications require the rpc backend, polling instead");
+                None
+            }
+            (None, _) => None,
+        };
+
+        let _ = std::thread::spawn(move || {
+            // Lives across iterations so the mempool isn't downloaded again on every poll
+            let mut emitter = None;
+            loop {
+                let result = match client.as_ref() {
+                    Client::Rpc(rpc_client) => {
+                        let emitter = emitter.get_or_insert_with(|| worker.rpc_emitter(rpc_client));
+                        worker.sync_rpc(emitter)
+                    }
+                    Client::Electrum(electrum_client) => worker.sync_electrum(electrum_client),
+                    Client::Esplora(esplora_client) => worker.sync_esplora(esplora_client),
+                };
+                if result.is_err() {
+                    // Start over from the wallet tip, the emitter may be mid-block
+                    emitter = None;
+                }
+                if let Err(e) = result
+                    .and_then(|_| worker.update_confirmations())
+                    .and_then(|_| worker.update_invoices())
+                {
+                    eprintln!("Sync failed: {}", e);
+                }
 
-                        let mut tx_details = Vec::<(String, u64, String)>::new();
+                if rx.try_recv().is_ok() {
+                    println!("Received stop signal, ending sync");
+                    break;
+                }
 
-                        // First pass: collect transaction details
-                        {
-                            let wallet = wallet_ref.lock().unwrap();
-                            block_event.block.txdata.iter().for_each(|tx| {
-                                tx.output.iter().for_each(|out| {
-                                    if wallet.is_mine(out.script_pubkey.clone()) {
-                                        if let Ok(address) = bitcoin::Address::from_script(
-                                            &out.script_pubkey,
-                                            wallet.network(),
-                                        ) {
-                                            let amount = out.value.to_sat();
-                                            tx_details.push((
-                                                address.to_string(),
-                                                amount,
-                                                tx.compute_txid().to_string(),
-                                            ));
-                                        }
-                                    }
-                                });
-                            });
-                        } // wallet lock released here
-
-                        // Second pass: apply block
-                        {
-                            let mut wallet = wallet_ref.lock().unwrap();
-                            wallet
-                                .apply_block_connected_to(
-                                    &block_event.block,
-                                    block_event.block_height(),
-                                    block_event.connected_to(),
-                                )
-                                .unwrap();
-                            wallet.persist(&mut conn_ref.lock().unwrap()).unwrap();
-                            println!("Block applied to wallet");
-                        } // wallet lock released here
-
-                        // Only publish if we found any deposits
-                        if !tx_details.is_empty() {
-                            if let Err(e) =
-                                publisher.lock().unwrap().publish(ChainEvent::NewDeposits {
-                                    deposits: tx_details,
-                                })
-                            {
-                                eprintln!("Failed to publish block details: {}", e);
-                            }
+                // Wake up as soon as bitcoind announces something, or poll when it stays silent
+                match &notifier {
+                    Some(notifier) => {
+                        if let Err(e) = notifier.wait(SYNC_INTERVAL) {
+                            eprintln!("Failed to receive notification: {}", e);
+                            sleep(SYNC_INTERVAL);
                         }
-
-                        println!("✓"); // Visual indicator of block completion
                     }
-                    if rx.try_recv().is_ok() {
-                        println!("Received stop signal, ending sync");
-                        break;
-                    }
-                    sleep(Duration::from_secs(5));
-                });
-            }
-            Client::Electrum(_electrum) => {
-                println!("Not supported");
+                    None => sleep(SYNC_INTERVAL),
+                }
             }
+        });
+    }
+
+    /// Trigger syncs from bitcoind's ZMQ `hashblock`/`rawtx` notifications published
+    /// on `endpoint` instead of only polling. Takes effect on the next [`sync`](Self::sync).
+    pub fn set_zmq_endpoint(&mut self, endpoint: &str) {
+        self.zmq_endpoint = Some(endpoint.to_string());
+    }
+
+    /// Refuse to run against a backend that serves a different chain than the wallet's
+    fn check_genesis(&self, genesis_hash: BlockHash) {
+        let network = self.bdk_wallet.lock().unwrap().network();
+        if genesis_hash != genesis_block(network).block_hash() {
+            panic!(
+                "Chain backend is not on {}, the network the wallet is configured for",
+                network
+            );
+        }
+    }
+
+    fn sync_worker(&self) -> SyncWorker {
+        SyncWorker {
+            wallet: self.bdk_wallet.clone(),
+            conn: self.conn.clone(),
+            publisher: self.publisher.clone(),
+            deposits: self.deposits.clone(),
+            customers: self.customers.clone(),
+            ledger: self.ledger.clone(),
+            invoices: self.invoices.clone(),
+            confirmation_threshold: self.confirmation_threshold,
         }
     }
 
@@ -217,17 +413,70 @@ impl BitServWallet {
     }
 
     pub fn reveal_next_address(&self) -> Result<String> {
-        let mut wallet = self.bdk_wallet.lock().unwrap();
-        let mut conn = self.conn.lock().unwrap();
-        let address = wallet.reveal_next_address(KeychainKind::External);
+        Ok(self.reveal_address()?.to_string())
+    }
+
+    fn reveal_address(&self) -> Result<AddressInfo> {
+        let address = {
+            let mut wallet = self.bdk_wallet.lock().unwrap();
+            let mut conn = self.conn.lock().unwrap();
+            let address = wallet.reveal_next_address(KeychainKind::External);
 
-        match wallet.persist(&mut conn) {
-            Ok(_) => Ok(address.to_string()),
-            Err(e) => {
+            if let Err(e) = wallet.persist(&mut conn) {
                 println!("Error persisting wallet: {}", e);
-                Err(e.into())
+                return Err(e.into());
+            }
+            address
+        }; // wallet lock released here
+
+        self.publish_new_address(address.to_string(), address.index, address.keychain);
+        Ok(address)
+    }
+
+    /// Deposit address of a customer. Returns the customer's latest address unless
+    /// `fresh` is set or they don't have one yet, in which case a new one is revealed.
+    pub fn customer_address(&self, customer_id: &str, fresh: bool) -> Result<CustomerAddress> {
+        if customer_id.trim().is_empty() {
+            return Err(anyhow!("Customer id must not be empty"));
+        }
+
+        let _assignment = self.customer_assignment.lock().unwrap();
+        if !fresh {
+            if let Some(address) = self.customers.latest_address(customer_id)? {
+                return Ok(address);
             }
         }
+
+        let address = self.reveal_address()?;
+        self.customers
+            .assign(customer_id, &address.to_string(), address.index)?;
+        Ok(CustomerAddress {
+            customer_id: customer_id.to_string(),
+            address: address.to_string(),
+            index: address.index,
+        })
+    }
+
+    pub fn find_customer_by_address(&self, address: &str) -> Result<Option<CustomerAddress>> {
+        self.customers.find_by_address(address)
+    }
+
+    /// Customer ids of the given addresses, leaving out unassigned addresses
+    pub fn customers_of<'a>(
+        &self,
+        addresses: impl IntoIterator<Item = &'a str>,
+    ) -> Result<BTreeMap<String, String>> {
+        self.customers.customers_of(addresses)
+    }
+
+    fn publish_new_address(&self, address: String, index: u32, keychain: KeychainKind) {
+        if let Err(e) = self.publish_chainevent(ChainEvent::NewAddress {
+            address,
+            index,
+            keychain,
+        }) {
+            eprintln!("Failed to publish new address {}: {}", index, e);
+        }
     }
 
     pub fn get_receiving_address_by_index(&self, index: u32) -> String {
@@ -320,8 +569,403 @@ impl BitServWallet {
         result
     }
 
+    /// Page of wallet transactions matching `filter`, unconfirmed first and then newest first
+    pub fn list_transactions(
+        &self,
+        filter: &TransactionFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<TransactionSummary> {
+        let wallet = self.bdk_wallet.lock().unwrap();
+        transactions::list_transactions(&wallet, filter, offset, limit)
+    }
+
+    /// Unspent outputs of the wallet, largest first
+    pub fn list_utxos(&self) -> Result<Vec<Utxo>> {
+        let frozen = self.frozen.outpoints()?;
+        let wallet = self.bdk_wallet.lock().unwrap();
+        Ok(coins::list_utxos(&wallet, &frozen))
+    }
+
+    /// Exclude an outpoint from every transaction the wallet builds
+    pub fn freeze_utxo(&self, outpoint: &str, reason: Option<&str>) -> Result<()> {
+        self.frozen.freeze(OutPoint::from_str(outpoint)?, reason)
+    }
+
+    /// Make a frozen outpoint spendable again. Returns whether it was frozen.
+    pub fn unfreeze_utxo(&self, outpoint: &str) -> Result<bool> {
+        self.frozen.unfreeze(OutPoint::from_str(outpoint)?)
+    }
+
+    /// Decoded wallet transaction, `None` if the wallet doesn't know it
+    pub fn get_transaction(&self, txid: &str) -> Result<Option<TransactionDetail>> {
+        let txid = Txid::from_str(txid)?;
+        let wallet = self.bdk_wallet.lock().unwrap();
+        Ok(transactions::transaction_detail(&wallet, txid))
+    }
+
+    /// Script of an address on the wallet's network
+    pub fn address_script(&self, address: &str) -> Result<ScriptBuf> {
+        let network = self.bdk_wallet.lock().unwrap().network();
+        Ok(Address::from_str(address)?
+            .require_network(network)?
+            .script_pubkey())
+    }
+
+    /// Build, sign and broadcast a transaction paying `amount` sats to `address`
+    /// at `fee_rate` sat/vB. Returns the txid of the broadcast transaction.
+    pub fn send(&self, address: &str, amount: u64, fee_rate: u64) -> Result<Txid> {
+        self.send_many(&[(address.to_string(), amount)], fee_rate)
+    }
+
+    /// Same as [`send`](Self::send) but pays every `(address, amount)` recipient
+    /// from a single transaction.
+    pub fn send_many(&self, recipients: &[(String, u64)], fee_rate: u64) -> Result<Txid> {
+        Ok(self.send_transaction(recipients, fee_rate, |_| Ok(()))?)
+    }
+
+    /// Build and sign the transaction, then run `before_broadcast` with its txid
+    /// before broadcasting it. The transaction is dropped if `before_broadcast` fails.
+    fn send_transaction(
+        &self,
+        recipients: &[(String, u64)],
+        fee_rate: u64,
+        before_broadcast: impl FnOnce(Txid) -> Result<()>,
+    ) -> std::result::Result<Txid, SendError> {
+        let client = self
+            .client
+            .as_ref()
+            .ok_or_else(|| SendError::NotSent(anyhow!("Wallet is not connected to a backend")))?;
+
+        let frozen = self.frozen.outpoints().map_err(SendError::NotSent)?;
+        let mut wallet = self.bdk_wallet.lock().unwrap();
+        let tx = build_transaction(&mut wallet, recipients, fee_rate, frozen)
+            .map_err(SendError::NotSent)?;
+        let txid = tx.compute_txid();
+
+        if let Err(e) = before_broadcast(txid) {
+            // Release the change address and inputs reserved by this transaction
+            wallet.cancel_tx(&tx);
+            return Err(SendError::NotSent(e));
+        }
+        if let Err(e) = broadcast(client, &tx) {
+            if !is_rejection(&e) {
+                return Err(SendError::MaybeSent(txid, e));
+            }
+            wallet.cancel_tx(&tx);
+            return Err(SendError::NotSent(e));
+        }
+
+        // The transaction is out, failing from here on doesn't unsend it
+        let last_seen = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| SendError::MaybeSent(txid, e.into()))?
+            .as_secs();
+        wallet.apply_unconfirmed_txs([(tx, last_seen)]);
+        wallet
+            .persist(&mut self.conn.lock().unwrap())
+            .map_err(|e| SendError::MaybeSent(txid, e.into()))?;
+
+        Ok(txid)
+    }
+
+    /// Queue a payout to be paid by the next batch transaction. Returns the withdrawal id.
+    /// Withdrawals made for a customer are debited from their ledger balance right
+    /// away and rejected if the balance doesn't cover them.
+    pub fn queue_withdrawal(
+        &self,
+        address: &str,
+        amount: u64,
+        customer_id: Option<&str>,
+    ) -> Result<i64> {
+        // Validate the payout up front so a bad request can't block the whole batch
+        let script = self.address_script(address)?;
+        let dust_limit = script.minimal_non_dust();
+        if amount < dust_limit.to_sat() {
+            return Err(anyhow!(
+                "Amount {} sats is below the dust limit of {} sats for {}",
+                amount,
+                dust_limit.to_sat(),
+                address
+            ));
+        }
+
+        match customer_id {
+            Some(customer_id) => self
+                .withdrawals
+                .enqueue_debited(address, amount, customer_id),
+            None => self.withdrawals.enqueue(address, amount),
+        }
+    }
+
+    /// Cancel a queued payout that hasn't been broadcast yet, refunding the customer's
+    /// ledger balance if it was debited. Returns `None` if there's no such withdrawal.
+    pub fn cancel_withdrawal(&self, id: i64) -> Result<Option<Withdrawal>> {
+        let Some(withdrawal) = self.withdrawals.cancel(id)? else {
+            return Ok(None);
+        };
+        println!("Cancelled withdrawal {}", id);
+        self.publish_withdrawal(withdrawal.clone(), "", WithdrawalStatus::Cancelled);
+        Ok(Some(withdrawal))
+    }
+
+    /// Request a payment of `amount` sats to a fresh address, due within `expires_in`
+    pub fn create_invoice(&self, amount: u64, expires_in: Duration) -> Result<Invoice> {
+        if amount == 0 {
+            return Err(anyhow!("Invoice amount must be positive"));
+        }
+        let expires_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs()
+            .checked_add(expires_in.as_secs())
+            .ok_or_else(|| anyhow!("Invoice expiry is too far in the future"))?;
+        let address = self.reveal_next_address()?;
+        let id = self.invoices.create(&address, amount, expires_at)?;
+        self.invoices
+            .get(id)?
+            .ok_or_else(|| anyhow!("Invoice {} disappeared", id))
+    }
+
+    pub fn get_invoice(&self, id: i64) -> Result<Option<Invoice>> {
+        self.invoices.get(id)
+    }
+
+    /// Ledger balance of a customer in sats
+    pub fn customer_balance(&self, customer_id: &str) -> Result<i64> {
+        self.ledger.balance(customer_id)
+    }
+
+    /// Ledger entries of a customer, newest first
+    pub fn customer_history(&self, customer_id: &str, limit: u32) -> Result<Vec<LedgerEntry>> {
+        self.ledger.history(customer_id, limit)
+    }
+
+    pub fn get_withdrawal(&self, id: i64) -> Result<Option<Withdrawal>> {
+        self.withdrawals.get(id)
+    }
+
+    /// Pay all pending withdrawals in one transaction and report confirmations
+    /// for previously broadcast batches.
+    pub fn process_withdrawals(&self, fee_rate: u64) -> Result<()> {
+        self.confirm_withdrawals()?;
+
+        let pending = self.withdrawals.list_by_status(WithdrawalStatus::Pending)?;
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let queued = pending.len();
+        let mut batch = pending;
+        let txid = loop {
+            let recipients: Vec<_> = batch
+                .iter()
+                .map(|withdrawal| (withdrawal.address.clone(), withdrawal.amount))
+                .collect();
+            let ids: Vec<_> = batch.iter().map(|withdrawal| withdrawal.id).collect();
+
+            // Record the txid before broadcasting, so a failure after the broadcast can't
+            // leave the payouts pending and get them paid twice by the next batch
+            let result = self.send_transaction(&recipients, fee_rate, |txid| {
+                self.withdrawals.mark_broadcast(&ids, &txid.to_string())
+            });
+            match result {
+                Ok(txid) => break txid.to_string(),
+                Err(SendError::NotSent(e)) => {
+                    self.withdrawals.mark_pending(&ids)?;
+                    // Pay the oldest payouts the wallet can afford rather than none
+                    if batch.len() > 1 && is_insufficient_funds(&e) {
+                        batch.pop();
+                        continue;
+                    }
+                    return Err(e);
+                }
+                Err(SendError::MaybeSent(txid, e)) => {
+                    eprintln!(
+                        "Withdrawal batch {} may have been broadcast, its {} payouts stay marked as broadcast: {}",
+                        txid,
+                        batch.len(),
+                        e
+                    );
+                    return Err(e);
+                }
+            }
+        };
+        println!(
+            "Broadcast withdrawal batch {} with {} payouts",
+            txid,
+            batch.len()
+        );
+        if batch.len() < queued {
+            eprintln!(
+                "Wallet can't cover all queued withdrawals, {} wait for more funds",
+                queued - batch.len()
+            );
+        }
+
+        for withdrawal in batch {
+            self.publish_withdrawal(withdrawal, &txid, WithdrawalStatus::Broadcast);
+        }
+
+        Ok(())
+    }
+
+    fn confirm_withdrawals(&self) -> Result<()> {
+        let broadcast = self
+            .withdrawals
+            .list_by_status(WithdrawalStatus::Broadcast)?;
+
+        let confirmed: Vec<_> = {
+            let wallet = self.bdk_wallet.lock().unwrap();
+            broadcast
+                .into_iter()
+                .filter(|withdrawal| {
+                    withdrawal
+                        .txid
+                        .as_deref()
+                        .and_then(|txid| Txid::from_str(txid).ok())
+                        .and_then(|txid| wallet.get_tx(txid))
+                        .is_some_and(|wallet_tx| wallet_tx.chain_position.is_confirmed())
+                })
+                .collect()
+        }; // wallet lock released here
+
+        if confirmed.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<_> = confirmed.iter().map(|withdrawal| withdrawal.id).collect();
+        self.withdrawals.mark_confirmed(&ids)?;
+
+        for withdrawal in confirmed {
+            let txid = withdrawal.txid.clone().unwrap_or_default();
+            self.publish_withdrawal(withdrawal, &txid, WithdrawalStatus::Confirmed);
+        }
+
+        Ok(())
+    }
+
+    fn publish_withdrawal(&self, withdrawal: Withdrawal, txid: &str, status: WithdrawalStatus) {
+        if let Err(e) = self.publish_chainevent(ChainEvent::Withdrawal {
+            id: withdrawal.id,
+            address: withdrawal.address,
+            amount: withdrawal.amount,
+            txid: txid.to_string(),
+            status,
+        }) {
+            eprintln!("Failed to publish withdrawal {}: {}", withdrawal.id, e);
+        }
+    }
+
+    /// Spawn a thread that processes the withdrawal queue every `interval`
+    pub fn start_withdrawal_scheduler(self: &Arc<Self>, interval: Duration, fee_rate: u64) {
+        let wallet = self.clone();
+        let _ = std::thread::spawn(move || loop {
+            sleep(interval);
+            if let Err(e) = wallet.process_withdrawals(fee_rate) {
+                eprintln!("Failed to process withdrawal queue: {}", e);
+            }
+        });
+    }
+
+    /// Published events with a sequence number greater than `seq`, oldest first
+    pub fn events_since(&self, seq: u64, limit: u32) -> Result<Vec<SequencedEvent>> {
+        self.outbox.since(seq, limit)
+    }
+
+    pub fn subscribe_events(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.publisher.lock().unwrap().subscribe()
+    }
+
     pub fn publish_chainevent(&self, event: ChainEvent) -> Result<()> {
         let publisher = self.publisher.lock().unwrap();
         publisher.publish(event)
     }
 }
+
+/// Why a transaction wasn't sent
+enum SendError {
+    /// Nothing reached the network: building or signing failed, or the backend rejected it
+    NotSent(anyhow::Error),
+    /// The transaction may have been broadcast, or was and recording it failed
+    MaybeSent(Txid, anyhow::Error),
+}
+
+impl From<SendError> for anyhow::Error {
+    fn from(e: SendError) -> Self {
+        match e {
+            SendError::NotSent(e) => e,
+            SendError::MaybeSent(txid, e) => e.context(format!("Transaction {}", txid)),
+        }
+    }
+}
+
+fn build_transaction(
+    wallet: &mut PersistedWallet<Connection>,
+    recipients: &[(String, u64)],
+    fee_rate: u64,
+    frozen: Vec<OutPoint>,
+) -> Result<Transaction> {
+    let fee_rate = FeeRate::from_sat_per_vb(fee_rate)
+        .ok_or_else(|| anyhow!("Fee rate {} sat/vB is out of range", fee_rate))?;
+
+    let mut outputs = Vec::with_capacity(recipients.len());
+    for (address, amount) in recipients {
+        let address = Address::from_str(address)?.require_network(wallet.network())?;
+        outputs.push((address.script_pubkey(), Amount::from_sat(*amount)));
+    }
+
+    let mut builder = wallet.build_tx();
+    builder
+        .set_recipients(outputs)
+        .fee_rate(fee_rate)
+        .unspendable(frozen);
+    let mut psbt = builder.finish()?;
+
+    let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+    if !finalized {
+        return Err(anyhow!("Failed to finalize transaction"));
+    }
+    Ok(psbt.extract_tx()?)
+}
+
+fn is_insufficient_funds(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<CreateTxError>(),
+        Some(CreateTxError::CoinSelection(_))
+    )
+}
+
+/// Whether a broadcast error is the backend refusing the transaction, as opposed to
+/// not knowing if it got through, like on a timeout
+fn is_rejection(e: &anyhow::Error) -> bool {
+    if let Some(e) = e.downcast_ref::<bitcoincore_rpc::Error>() {
+        return matches!(
+            e,
+            bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(_))
+        );
+    }
+    if let Some(e) = e.downcast_ref::<electrum_client::Error>() {
+        return matches!(e, electrum_client::Error::Protocol(_));
+    }
+    if let Some(e) = e.downcast_ref::<esplora_client::Error>() {
+        return matches!(e, esplora_client::Error::HttpResponse { status, .. } if (400..500).contains(status));
+    }
+    false
+}
+
+fn broadcast(client: &Client, tx: &Transaction) -> Result<()> {
+    match client {
+        Client::Rpc(rpc_client) => {
+            rpc_client.send_raw_transaction(tx)?;
+            Ok(())
+        }
+        Client::Electrum(electrum_client) => {
+            electrum_client.transaction_broadcast(tx)?;
+            Ok(())
+        }
+        Client::Esplora(esplora_client) => {
+            esplora_client.broadcast(tx)?;
+            Ok(())
+        }
+    }
+}
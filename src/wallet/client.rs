@@ -1,6 +1,7 @@
 pub enum Client {
     Rpc(bdk_bitcoind_rpc::bitcoincore_rpc::Client),
-    Electrum(bdk_electrum::electrum_client::Client),
+    Electrum(Box<bdk_electrum::BdkElectrumClient<bdk_electrum::electrum_client::Client>>),
+    Esplora(bdk_esplora::esplora_client::BlockingClient),
 }
 
 impl Client {
@@ -10,6 +11,10 @@ impl Client {
     }
     pub fn new_electrum(url: &str) -> Self {
         let client = bdk_electrum::electrum_client::Client::new(url).unwrap();
-        Client::Electrum(client)
+        Client::Electrum(Box::new(bdk_electrum::BdkElectrumClient::new(client)))
+    }
+    pub fn new_esplora(url: &str) -> Self {
+        let client = bdk_esplora::esplora_client::Builder::new(url).build_blocking();
+        Client::Esplora(client)
     }
 }
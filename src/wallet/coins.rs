@@ -0,0 +1,129 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use bdk_wallet::{
+    bitcoin::{Address, OutPoint},
+    chain::ChainPosition,
+    rusqlite::{self, params, Connection},
+    KeychainKind, PersistedWallet,
+};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    pub outpoint: String, // txid:vout
+    pub value: u64,       // in sats
+    pub address: Option<String>,
+    pub keychain: KeychainKind,
+    pub index: u32,
+    pub confirmations: u32,
+    pub frozen: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FrozenOutpoint {
+    pub outpoint: String,
+    pub reason: Option<String>,
+    pub frozen_at: u64,
+}
+
+/// Outpoints excluded from coin selection, e.g. quarantined deposits
+#[derive(Clone)]
+pub struct FrozenCoins {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl FrozenCoins {
+    pub fn new(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+        conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS bitserv_frozen_outpoints (
+                outpoint TEXT PRIMARY KEY,
+                reason TEXT,
+                frozen_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Freeze an outpoint, updating the reason if it's already frozen
+    pub fn freeze(&self, outpoint: OutPoint, reason: Option<&str>) -> Result<()> {
+        let frozen_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO bitserv_frozen_outpoints (outpoint, reason, frozen_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(outpoint) DO UPDATE SET reason = excluded.reason",
+            params![outpoint.to_string(), reason, frozen_at],
+        )?;
+        Ok(())
+    }
+
+    /// Returns whether the outpoint was frozen
+    pub fn unfreeze(&self, outpoint: OutPoint) -> Result<bool> {
+        let removed = self.conn.lock().unwrap().execute(
+            "DELETE FROM bitserv_frozen_outpoints WHERE outpoint = ?1",
+            params![outpoint.to_string()],
+        )?;
+        Ok(removed > 0)
+    }
+
+    pub fn list(&self) -> Result<Vec<FrozenOutpoint>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT outpoint, reason, frozen_at FROM bitserv_frozen_outpoints ORDER BY frozen_at",
+        )?;
+        let frozen = stmt
+            .query_map([], |row| {
+                Ok(FrozenOutpoint {
+                    outpoint: row.get(0)?,
+                    reason: row.get(1)?,
+                    frozen_at: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(frozen)
+    }
+
+    pub fn outpoints(&self) -> Result<Vec<OutPoint>> {
+        self.list()?
+            .into_iter()
+            .map(|frozen| Ok(OutPoint::from_str(&frozen.outpoint)?))
+            .collect()
+    }
+}
+
+/// Unspent outputs of the wallet, largest first
+pub fn list_utxos(wallet: &PersistedWallet<Connection>, frozen: &[OutPoint]) -> Vec<Utxo> {
+    let tip_height = wallet.latest_checkpoint().height();
+    let mut utxos: Vec<_> = wallet
+        .list_unspent()
+        .map(|utxo| {
+            let confirmations = match utxo.chain_position {
+                ChainPosition::Confirmed { anchor, .. } => {
+                    tip_height.saturating_sub(anchor.block_id.height) + 1
+                }
+                ChainPosition::Unconfirmed { .. } => 0,
+            };
+            Utxo {
+                outpoint: utxo.outpoint.to_string(),
+                value: utxo.txout.value.to_sat(),
+                address: Address::from_script(&utxo.txout.script_pubkey, wallet.network())
+                    .ok()
+                    .map(|address| address.to_string()),
+                keychain: utxo.keychain,
+                index: utxo.derivation_index,
+                confirmations,
+                frozen: frozen.contains(&utxo.outpoint),
+            }
+        })
+        .collect();
+    utxos.sort_by(|a, b| {
+        b.value
+            .cmp(&a.value)
+            .then_with(|| a.outpoint.cmp(&b.outpoint))
+    });
+    utxos
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use bdk_wallet::rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CustomerAddress {
+    pub customer_id: String,
+    pub address: String,
+    pub index: u32,
+}
+
+/// Deposit addresses handed out to each customer, so deposits can be
+/// attributed without keeping the mapping in another database.
+#[derive(Clone)]
+pub struct CustomerRegistry {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl CustomerRegistry {
+    pub fn new(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+        conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS bitserv_customer_addresses (
+                address TEXT PRIMARY KEY,
+                customer_id TEXT NOT NULL,
+                derivation_index INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        conn.lock().unwrap().execute(
+            "CREATE INDEX IF NOT EXISTS bitserv_customer_addresses_customer
+             ON bitserv_customer_addresses (customer_id)",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    pub fn assign(&self, customer_id: &str, address: &str, index: u32) -> Result<()> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO bitserv_customer_addresses
+             (address, customer_id, derivation_index, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![address, customer_id, index, created_at],
+        )?;
+        Ok(())
+    }
+
+    /// The most recently assigned address of a customer
+    pub fn latest_address(&self, customer_id: &str) -> Result<Option<CustomerAddress>> {
+        let conn = self.conn.lock().unwrap();
+        let address = conn
+            .query_row(
+                "SELECT customer_id, address, derivation_index FROM bitserv_customer_addresses
+                 WHERE customer_id = ?1 ORDER BY derivation_index DESC LIMIT 1",
+                params![customer_id],
+                |row| {
+                    Ok(CustomerAddress {
+                        customer_id: row.get(0)?,
+                        address: row.get(1)?,
+                        index: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(address)
+    }
+
+    pub fn find_by_address(&self, address: &str) -> Result<Option<CustomerAddress>> {
+        let conn = self.conn.lock().unwrap();
+        let address = conn
+            .query_row(
+                "SELECT customer_id, address, derivation_index FROM bitserv_customer_addresses
+                 WHERE address = ?1",
+                params![address],
+                |row| {
+                    Ok(CustomerAddress {
+                        customer_id: row.get(0)?,
+                        address: row.get(1)?,
+                        index: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(address)
+    }
+
+    /// Customer ids of the given addresses, leaving out unassigned addresses
+    pub fn customers_of<'a>(
+        &self,
+        addresses: impl IntoIterator<Item = &'a str>,
+    ) -> Result<BTreeMap<String, String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT customer_id FROM bitserv_customer_addresses WHERE address = ?1")?;
+        let mut customers = BTreeMap::new();
+        for address in addresses {
+            if let Some(customer_id) = stmt
+                .query_row(params![address], |row| row.get::<_, String>(0))
+                .optional()?
+            {
+                customers.insert(address.to_string(), customer_id);
+            }
+        }
+        Ok(customers)
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use bdk_wallet::rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrackedDeposit {
+    pub txid: String,
+    pub amount: u64,
+    pub confirmations: u32,
+    pub credited: bool,
+}
+
+/// Confirmation progress of incoming deposits, so every confirmation update and
+/// the final credit are only published once across restarts.
+#[derive(Clone)]
+pub struct DepositTracker {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl DepositTracker {
+    pub fn new(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+        conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS bitserv_deposits (
+                txid TEXT PRIMARY KEY,
+                amount INTEGER NOT NULL,
+                confirmations INTEGER NOT NULL,
+                credited INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    pub fn get(&self, txid: &str) -> Result<Option<TrackedDeposit>> {
+        let conn = self.conn.lock().unwrap();
+        let deposit = conn
+            .query_row(
+                "SELECT txid, amount, confirmations, credited FROM bitserv_deposits
+                 WHERE txid = ?1",
+                params![txid],
+                |row| {
+                    Ok(TrackedDeposit {
+                        txid: row.get(0)?,
+                        amount: row.get(1)?,
+                        confirmations: row.get(2)?,
+                        credited: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(deposit)
+    }
+
+    /// Record the latest confirmation count of a deposit, tracking it if it's new
+    pub fn set_confirmations(&self, txid: &str, amount: u64, confirmations: u32) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO bitserv_deposits (txid, amount, confirmations) VALUES (?1, ?2, ?3)
+             ON CONFLICT(txid) DO UPDATE SET confirmations = excluded.confirmations",
+            params![txid, amount, confirmations],
+        )?;
+        Ok(())
+    }
+
+    pub fn mark_credited(&self, txid: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE bitserv_deposits SET credited = 1 WHERE txid = ?1",
+            params![txid],
+        )?;
+        Ok(())
+    }
+
+    /// Forget the confirmation progress of a deposit that was reorged out
+    pub fn reset(&self, txid: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE bitserv_deposits SET confirmations = 0, credited = 0 WHERE txid = ?1",
+            params![txid],
+        )?;
+        Ok(())
+    }
+
+    pub fn credited_txids(&self) -> Result<HashSet<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT txid FROM bitserv_deposits WHERE credited = 1")?;
+        let txids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(txids)
+    }
+}
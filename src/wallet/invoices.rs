@@ -0,0 +1,195 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use bdk_wallet::{
+    bitcoin::{Amount, Denomination},
+    rusqlite::{self, params, types::Type, Connection, Row},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InvoiceStatus {
+    Pending,
+    Paid,
+    Underpaid,
+    Overpaid,
+    Expired,
+}
+
+impl InvoiceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvoiceStatus::Pending => "pending",
+            InvoiceStatus::Paid => "paid",
+            InvoiceStatus::Underpaid => "underpaid",
+            InvoiceStatus::Overpaid => "overpaid",
+            InvoiceStatus::Expired => "expired",
+        }
+    }
+
+    /// Status of an invoice for `amount` sats after `received` sats arrived
+    pub fn evaluate(amount: u64, received: u64, expired: bool) -> Self {
+        match received {
+            0 if expired => InvoiceStatus::Expired,
+            0 => InvoiceStatus::Pending,
+            received if received < amount => InvoiceStatus::Underpaid,
+            received if received == amount => InvoiceStatus::Paid,
+            _ => InvoiceStatus::Overpaid,
+        }
+    }
+}
+
+impl FromStr for InvoiceStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(InvoiceStatus::Pending),
+            "paid" => Ok(InvoiceStatus::Paid),
+            "underpaid" => Ok(InvoiceStatus::Underpaid),
+            "overpaid" => Ok(InvoiceStatus::Overpaid),
+            "expired" => Ok(InvoiceStatus::Expired),
+            _ => Err(anyhow!("Unknown invoice status: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Invoice {
+    pub id: i64,
+    pub address: String,
+    pub amount: u64,    // expected, in sats
+    pub received: u64,  // in sats, including unconfirmed payments
+    pub confirmed: u64, // in sats, with at least the confirmation threshold
+    pub status: InvoiceStatus,
+    pub expires_at: u64,
+}
+
+impl Invoice {
+    /// BIP21 payment URI for the invoice
+    pub fn uri(&self) -> String {
+        format!(
+            "bitcoin:{}?amount={}",
+            self.address,
+            Amount::from_sat(self.amount).to_string_in(Denomination::Bitcoin)
+        )
+    }
+}
+
+/// How long an expired invoice is still watched, so a late payment is reported
+pub const EXPIRED_GRACE_PERIOD: u64 = 7 * 24 * 60 * 60;
+
+/// Payment requests for a fixed amount, each paid to its own address.
+/// Stored in the wallet's SQLite database next to the bdk tables.
+#[derive(Clone)]
+pub struct InvoiceBook {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl InvoiceBook {
+    pub fn new(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+        conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS bitserv_invoices (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                address TEXT NOT NULL UNIQUE,
+                amount INTEGER NOT NULL,
+                received INTEGER NOT NULL DEFAULT 0,
+                confirmed INTEGER NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Open an invoice paid to `address`, returning its id
+    pub fn create(&self, address: &str, amount: u64, expires_at: u64) -> Result<i64> {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO bitserv_invoices (address, amount, status, expires_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                address,
+                amount,
+                InvoiceStatus::Pending.as_str(),
+                expires_at,
+                created_at
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<Invoice>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, address, amount, received, confirmed, status, expires_at FROM bitserv_invoices
+             WHERE id = ?1",
+        )?;
+        let mut rows = stmt.query_map(params![id], row_to_invoice)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Invoices that can still change status: pending and underpaid ones, and paid ones
+    /// until enough of the payment confirmed, since an unconfirmed payment can be double-spent.
+    /// Expired ones stay open for `EXPIRED_GRACE_PERIOD` to catch payments sent after expiry.
+    pub fn list_open(&self) -> Result<Vec<Invoice>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, address, amount, received, confirmed, status, expires_at
+             FROM bitserv_invoices
+             WHERE status IN (?1, ?2) OR (status IN (?3, ?4) AND confirmed < amount)
+                OR (status = ?5 AND expires_at > ?6)
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                InvoiceStatus::Pending.as_str(),
+                InvoiceStatus::Underpaid.as_str(),
+                InvoiceStatus::Paid.as_str(),
+                InvoiceStatus::Overpaid.as_str(),
+                InvoiceStatus::Expired.as_str(),
+                now.saturating_sub(EXPIRED_GRACE_PERIOD)
+            ],
+            row_to_invoice,
+        )?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn update(
+        &self,
+        id: i64,
+        received: u64,
+        confirmed: u64,
+        status: InvoiceStatus,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE bitserv_invoices SET received = ?1, confirmed = ?2, status = ?3 WHERE id = ?4",
+            params![received, confirmed, status.as_str(), id],
+        )?;
+        Ok(())
+    }
+}
+
+fn row_to_invoice(row: &Row) -> rusqlite::Result<Invoice> {
+    let status: String = row.get(5)?;
+    let status = InvoiceStatus::from_str(&status)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, e.into()))?;
+    Ok(Invoice {
+        id: row.get(0)?,
+        address: row.get(1)?,
+        amount: row.get(2)?,
+        received: row.get(3)?,
+        confirmed: row.get(4)?,
+        status,
+        expires_at: row.get(6)?,
+    })
+}
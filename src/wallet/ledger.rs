@@ -0,0 +1,253 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use bdk_wallet::rusqlite::{self, params, Connection};
+use serde::Serialize;
+
+/// Counterpart of every customer entry: the coins the wallet holds on customers' behalf
+const WALLET_ACCOUNT: &str = "wallet";
+
+const DEPOSIT: &str = "deposit";
+const DEPOSIT_REVERSAL: &str = "deposit_reversal";
+const WITHDRAWAL: &str = "withdrawal";
+const WITHDRAWAL_REFUND: &str = "withdrawal_refund";
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub transaction_id: i64,
+    pub kind: String, // deposit, deposit_reversal, withdrawal or withdrawal_refund
+    pub reference: String, // deposit txid or withdrawal id
+    pub amount: i64,  // in sats, negative for debits
+    pub created_at: u64,
+}
+
+/// Double-entry ledger of customer balances. Every transaction moves sats between
+/// customer accounts and the wallet account, so all entries of a transaction sum to zero.
+#[derive(Clone)]
+pub struct Ledger {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Ledger {
+    pub fn new(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+        conn.lock().unwrap().execute_batch(
+            "CREATE TABLE IF NOT EXISTS bitserv_ledger_transactions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                reference TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS bitserv_ledger_entries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                transaction_id INTEGER NOT NULL REFERENCES bitserv_ledger_transactions (id),
+                account TEXT NOT NULL,
+                amount INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS bitserv_ledger_entries_account
+                ON bitserv_ledger_entries (account);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Credit customers for a deposit that reached the confirmation threshold.
+    /// Does nothing if the deposit is already credited.
+    pub fn credit_deposit(&self, txid: &str, credits: &[(String, u64)]) -> Result<()> {
+        if credits.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        if is_credited(&db_tx, txid)? {
+            return Ok(());
+        }
+
+        let total: u64 = credits.iter().map(|(_, amount)| amount).sum();
+        let mut entries: Vec<(String, i64)> = credits
+            .iter()
+            .map(|(customer_id, amount)| (customer_account(customer_id), *amount as i64))
+            .collect();
+        entries.push((WALLET_ACCOUNT.to_string(), -(total as i64)));
+
+        post(&db_tx, DEPOSIT, txid, &entries)?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Take back the credit of a deposit whose block was reorged out
+    pub fn reverse_deposit(&self, txid: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        if !is_credited(&db_tx, txid)? {
+            return Ok(());
+        }
+
+        // Negate the entries of the latest credit
+        let entries = db_tx
+            .prepare(
+                "SELECT account, amount FROM bitserv_ledger_entries WHERE transaction_id = (
+                    SELECT MAX(id) FROM bitserv_ledger_transactions
+                    WHERE kind = ?1 AND reference = ?2
+                 )",
+            )?
+            .query_map(params![DEPOSIT, txid], |row| {
+                Ok((row.get::<_, String>(0)?, -row.get::<_, i64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        post(&db_tx, DEPOSIT_REVERSAL, txid, &entries)?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Debit a customer for a queued withdrawal, failing if their balance is too low
+    pub fn debit_withdrawal(
+        &self,
+        customer_id: &str,
+        withdrawal_id: i64,
+        amount: u64,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        debit_withdrawal(&db_tx, customer_id, withdrawal_id, amount)?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    pub fn balance(&self, customer_id: &str) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        account_balance(&conn, &customer_account(customer_id))
+    }
+
+    /// Entries of a customer's account, newest first
+    pub fn history(&self, customer_id: &str, limit: u32) -> Result<Vec<LedgerEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.kind, t.reference, e.amount, t.created_at
+             FROM bitserv_ledger_entries e
+             JOIN bitserv_ledger_transactions t ON t.id = e.transaction_id
+             WHERE e.account = ?1 ORDER BY e.id DESC LIMIT ?2",
+        )?;
+        let entries = stmt
+            .query_map(params![customer_account(customer_id), limit], |row| {
+                Ok(LedgerEntry {
+                    transaction_id: row.get(0)?,
+                    kind: row.get(1)?,
+                    reference: row.get(2)?,
+                    amount: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+}
+
+fn customer_account(customer_id: &str) -> String {
+    format!("customer:{}", customer_id)
+}
+
+fn account_balance(conn: &Connection, account: &str) -> Result<i64> {
+    let balance = conn.query_row(
+        "SELECT COALESCE(SUM(amount), 0) FROM bitserv_ledger_entries WHERE account = ?1",
+        params![account],
+        |row| row.get(0),
+    )?;
+    Ok(balance)
+}
+
+/// Debit a customer for a withdrawal within the caller's database transaction,
+/// failing if their balance is too low
+pub(crate) fn debit_withdrawal(
+    conn: &Connection,
+    customer_id: &str,
+    withdrawal_id: i64,
+    amount: u64,
+) -> Result<()> {
+    let account = customer_account(customer_id);
+    let balance = account_balance(conn, &account)?;
+    if balance < amount as i64 {
+        return Err(anyhow!(
+            "Insufficient balance: {} has {} sats, {} requested",
+            customer_id,
+            balance,
+            amount
+        ));
+    }
+
+    let entries = [
+        (account, -(amount as i64)),
+        (WALLET_ACCOUNT.to_string(), amount as i64),
+    ];
+    post(conn, WITHDRAWAL, &withdrawal_id.to_string(), &entries)
+}
+
+/// Give back the debit of a withdrawal that won't be paid, within the caller's
+/// database transaction. Does nothing if it wasn't debited or is already refunded.
+pub(crate) fn refund_withdrawal(conn: &Connection, withdrawal_id: i64) -> Result<()> {
+    let reference = withdrawal_id.to_string();
+    let refunded: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM bitserv_ledger_transactions
+         WHERE kind = ?1 AND reference = ?2)",
+        params![WITHDRAWAL_REFUND, reference],
+        |row| row.get(0),
+    )?;
+    if refunded {
+        return Ok(());
+    }
+
+    let entries = conn
+        .prepare(
+            "SELECT e.account, e.amount FROM bitserv_ledger_entries e
+             JOIN bitserv_ledger_transactions t ON t.id = e.transaction_id
+             WHERE t.kind = ?1 AND t.reference = ?2",
+        )?
+        .query_map(params![WITHDRAWAL, reference], |row| {
+            Ok((row.get::<_, String>(0)?, -row.get::<_, i64>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    post(conn, WITHDRAWAL_REFUND, &reference, &entries)
+}
+
+/// A deposit is credited if it has more credits than reversals
+fn is_credited(conn: &Connection, txid: &str) -> Result<bool> {
+    let net: i64 = conn.query_row(
+        "SELECT COALESCE(SUM(CASE kind WHEN ?1 THEN 1 ELSE -1 END), 0)
+         FROM bitserv_ledger_transactions WHERE reference = ?3 AND kind IN (?1, ?2)",
+        params![DEPOSIT, DEPOSIT_REVERSAL, txid],
+        |row| row.get(0),
+    )?;
+    Ok(net > 0)
+}
+
+fn post(conn: &Connection, kind: &str, reference: &str, entries: &[(String, i64)]) -> Result<()> {
+    if entries.iter().map(|(_, amount)| amount).sum::<i64>() != 0 {
+        return Err(anyhow!(
+            "Unbalanced ledger transaction {} {}",
+            kind,
+            reference
+        ));
+    }
+
+    let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    conn.execute(
+        "INSERT INTO bitserv_ledger_transactions (kind, reference, created_at)
+         VALUES (?1, ?2, ?3)",
+        params![kind, reference, created_at],
+    )?;
+    let transaction_id = conn.last_insert_rowid();
+    for (account, amount) in entries {
+        conn.execute(
+            "INSERT INTO bitserv_ledger_entries (transaction_id, account, amount)
+             VALUES (?1, ?2, ?3)",
+            params![transaction_id, account, amount],
+        )?;
+    }
+    Ok(())
+}
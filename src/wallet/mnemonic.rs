@@ -12,10 +12,115 @@ use chacha20poly1305::{
 };
 use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Version of the mnemonic file format written by `save_seed`.
+/// Files without a version predate the stored KDF parameters, and
+/// before version 2 the plaintext was the bare mnemonic without a passphrase.
+const FORMAT_VERSION: u32 = 2;
+
+/// What the wallet keys are derived from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Seed {
+    pub mnemonic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>, // BIP39 passphrase, the "25th word"
+}
+
+impl Seed {
+    /// Apply a passphrase supplied at startup rather than stored with the mnemonic.
+    /// Fails if it contradicts the stored one, as that would derive another wallet.
+    pub fn with_passphrase(self, passphrase: Option<String>) -> Result<Self> {
+        match (&self.passphrase, passphrase) {
+            (Some(stored), Some(supplied)) if *stored != supplied => Err(anyhow!(
+                "The supplied BIP39 passphrase differs from the stored one"
+            )),
+            (None, Some(supplied)) => Ok(Seed {
+                passphrase: Some(supplied),
+                ..self
+            }),
+            _ => Ok(self),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Argon2Variant {
+    Argon2d,
+    Argon2i,
+    Argon2id,
+}
+
+impl From<Argon2Variant> for argon2::Algorithm {
+    fn from(variant: Argon2Variant) -> Self {
+        match variant {
+            Argon2Variant::Argon2d => argon2::Algorithm::Argon2d,
+            Argon2Variant::Argon2i => argon2::Algorithm::Argon2i,
+            Argon2Variant::Argon2id => argon2::Algorithm::Argon2id,
+        }
+    }
+}
+
+/// Argon2 parameters the encryption key is derived with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub variant: Argon2Variant,
+    pub memory: u32, // in KiB
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Lowest costs accepted for newly written files, those of `Argon2::default()`
+    pub const MIN_MEMORY: u32 = argon2::Params::DEFAULT_M_COST;
+    pub const MIN_ITERATIONS: u32 = argon2::Params::DEFAULT_T_COST;
+
+    /// Fails if the costs are below the minimums
+    pub fn check_minimum(&self) -> Result<()> {
+        if self.memory < Self::MIN_MEMORY || self.iterations < Self::MIN_ITERATIONS {
+            return Err(anyhow!(
+                "Argon2 costs of {} KiB and {} iterations are below the minimum of {} KiB and {} iterations",
+                self.memory,
+                self.iterations,
+                Self::MIN_MEMORY,
+                Self::MIN_ITERATIONS
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether keys derived with these parameters cost at least as much as with `other`
+    pub fn is_at_least_as_strong_as(&self, other: &KdfParams) -> bool {
+        self.variant == other.variant
+            && self.memory >= other.memory
+            && self.iterations >= other.iterations
+            && self.parallelism >= other.parallelism
+    }
+}
+
+impl Default for KdfParams {
+    /// The parameters of `Argon2::default()`, used by files without stored parameters
+    fn default() -> Self {
+        Self {
+            variant: Argon2Variant::Argon2id,
+            memory: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 struct EncryptedMnemonic {
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    kdf: KdfParams,
     encrypted_data: Vec<u8>,
     nonce: Vec<u8>,
     salt: Vec<u8>,
@@ -23,40 +128,92 @@ struct EncryptedMnemonic {
 
 pub struct MnemonicStorage {
     storage_path: PathBuf,
+    kdf: KdfParams,
 }
 
 impl MnemonicStorage {
     pub fn new(storage_path: PathBuf) -> Self {
-        Self { storage_path }
+        Self {
+            storage_path,
+            kdf: KdfParams::default(),
+        }
+    }
+
+    /// Derive keys of newly written files with `kdf`. Existing files are only
+    /// re-encrypted with it when it's at least as strong as their own parameters.
+    pub fn with_kdf(mut self, kdf: KdfParams) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    pub fn exists(&self) -> bool {
+        self.storage_path.exists()
     }
 
-    pub fn load_or_create_by_password(&self, password: &str) -> String {
-        let mnemonic_words = match self.load_mnemonic(password) {
-            Ok(mnemonic_words) => mnemonic_words,
-            Err(_) => {
-                println!("Creating new mnemonic");
-                // Generate fresh mnemonic
-                let mnemonic: GeneratedKey<_, miniscript::Segwitv0> =
-                    Mnemonic::generate((WordCount::Words12, Language::English)).unwrap();
-                let mnemonic_words = mnemonic.to_string();
+    /// Unlock the stored seed, generating a fresh mnemonic only if there is no file yet.
+    /// A file that fails to decrypt is an error, never a reason to create a new wallet.
+    pub fn load_or_create_by_password(&self, password: &str) -> Result<Seed> {
+        if self.exists() {
+            return self.unlock(password);
+        }
+
+        println!("Creating new mnemonic");
+        // Generate fresh mnemonic
+        let mnemonic: GeneratedKey<_, miniscript::Segwitv0> =
+            Mnemonic::generate((WordCount::Words12, Language::English))
+                .map_err(|e| anyhow!("Failed to generate mnemonic: {:?}", e))?;
+        let seed = Seed {
+            mnemonic: mnemonic.to_string(),
+            passphrase: None,
+        };
+
+        // Save the mnemonic
+        self.save_seed(&seed, password)?;
+        println!("New wallet created and saved");
+
+        Ok(seed)
+    }
 
-                // Save the mnemonic
-                self.save_mnemonic(&mnemonic_words, password).unwrap();
-                println!("New wallet created and saved");
+    /// Import an existing BIP39 mnemonic of 12, 15, 18, 21 or 24 words.
+    /// Refuses to overwrite the mnemonic of an existing wallet.
+    pub fn restore(&self, mnemonic: &str, passphrase: Option<&str>, password: &str) -> Result<()> {
+        if self.exists() {
+            return Err(anyhow!(
+                "A mnemonic is already stored at {}",
+                self.storage_path.display()
+            ));
+        }
 
-                self.load_mnemonic(password).unwrap()
-            }
+        let mnemonic = Mnemonic::parse_in(Language::English, mnemonic)
+            .map_err(|e| anyhow!("Invalid mnemonic: {}", e))?;
+        let seed = Seed {
+            mnemonic: mnemonic.to_string(),
+            passphrase: passphrase
+                .filter(|passphrase| !passphrase.is_empty())
+                .map(String::from),
         };
-        mnemonic_words
+        self.save_seed(&seed, password)
     }
 
     pub fn save_mnemonic(&self, mnemonic: &str, password: &str) -> Result<()> {
+        let seed = Seed {
+            mnemonic: mnemonic.to_string(),
+            passphrase: None,
+        };
+        self.save_seed(&seed, password)
+    }
+
+    pub fn save_seed(&self, seed: &Seed, password: &str) -> Result<()> {
+        self.write_seed(seed, password, self.kdf)
+    }
+
+    fn write_seed(&self, seed: &Seed, password: &str, kdf: KdfParams) -> Result<()> {
         // Generate a random salt
         let mut salt = [0u8; 32];
         OsRng.fill_bytes(&mut salt);
 
         // Derive encryption key from password
-        let key = derive_key(password, &salt)?;
+        let key = derive_key(password, &salt, &kdf)?;
 
         // Generate a random nonce
         let mut nonce_bytes = [0u8; 12];
@@ -67,10 +224,12 @@ impl MnemonicStorage {
         let cipher = ChaCha20Poly1305::new_from_slice(&key)
             .map_err(|e| anyhow!("Invalid key length: {}", e))?;
         let encrypted_data = cipher
-            .encrypt(nonce, mnemonic.as_bytes())
+            .encrypt(nonce, serde_json::to_vec(seed)?.as_ref())
             .map_err(|e| anyhow!("Encryption failed: {}", e))?;
 
         let encrypted_mnemonic = EncryptedMnemonic {
+            version: FORMAT_VERSION,
+            kdf,
             encrypted_data,
             nonce: nonce_bytes.to_vec(),
             salt: salt.to_vec(),
@@ -78,35 +237,111 @@ impl MnemonicStorage {
 
         // Save to file
         let json = serde_json::to_string(&encrypted_mnemonic)?;
-        fs::write(&self.storage_path, json)?;
+        write_atomically(&self.storage_path, json.as_bytes())?;
 
         Ok(())
     }
 
+    /// Re-encrypt the mnemonic under a new password, with a fresh salt and nonce.
+    /// Fails without touching the file if `old_password` doesn't decrypt it.
+    pub fn change_password(&self, old_password: &str, new_password: &str) -> Result<()> {
+        let encrypted_mnemonic = self.read()?;
+        let seed = decrypt(&encrypted_mnemonic, old_password)?;
+        self.write_seed(&seed, new_password, self.kdf_for(&encrypted_mnemonic.kdf))
+    }
+
+    /// Decrypt the mnemonic, re-encrypting it if the file is in an older format
+    /// or the configured KDF parameters are stronger than the file's
+    pub fn unlock(&self, password: &str) -> Result<Seed> {
+        let encrypted_mnemonic = self.read()?;
+        let seed = decrypt(&encrypted_mnemonic, password)?;
+        let kdf = self.kdf_for(&encrypted_mnemonic.kdf);
+        if encrypted_mnemonic.version < FORMAT_VERSION || kdf != encrypted_mnemonic.kdf {
+            self.write_seed(&seed, password, kdf)?;
+            println!(
+                "Upgraded mnemonic file from version {} to {}",
+                encrypted_mnemonic.version, FORMAT_VERSION
+            );
+        }
+        Ok(seed)
+    }
+
+    /// KDF parameters to re-encrypt a file written with `stored` with:
+    /// the configured ones, unless that would weaken the encryption
+    fn kdf_for(&self, stored: &KdfParams) -> KdfParams {
+        if self.kdf.is_at_least_as_strong_as(stored) {
+            return self.kdf;
+        }
+        if self.kdf != *stored {
+            eprintln!(
+                "Configured Argon2 parameters are weaker than those of {}, keeping the file's",
+                self.storage_path.display()
+            );
+        }
+        *stored
+    }
+
     pub fn load_mnemonic(&self, password: &str) -> Result<String> {
+        Ok(decrypt(&self.read()?, password)?.mnemonic)
+    }
+
+    fn read(&self) -> Result<EncryptedMnemonic> {
         if !self.storage_path.exists() {
             return Err(anyhow!("Mnemonic file does not exist"));
         }
 
         let json = fs::read_to_string(&self.storage_path)?;
         let encrypted_mnemonic: EncryptedMnemonic = serde_json::from_str(&json)?;
+        if encrypted_mnemonic.version > FORMAT_VERSION {
+            return Err(anyhow!(
+                "Unsupported mnemonic file version {}",
+                encrypted_mnemonic.version
+            ));
+        }
+        Ok(encrypted_mnemonic)
+    }
+}
 
-        let key = derive_key(password, &encrypted_mnemonic.salt)?;
-        let cipher = ChaCha20Poly1305::new_from_slice(&key)
-            .map_err(|e| anyhow!("Invalid key length: {}", e))?;
-        let nonce = Nonce::from_slice(&encrypted_mnemonic.nonce);
+fn decrypt(encrypted_mnemonic: &EncryptedMnemonic, password: &str) -> Result<Seed> {
+    let key = derive_key(password, &encrypted_mnemonic.salt, &encrypted_mnemonic.kdf)?;
+    let cipher =
+        ChaCha20Poly1305::new_from_slice(&key).map_err(|e| anyhow!("Invalid key length: {}", e))?;
+    let nonce = Nonce::from_slice(&encrypted_mnemonic.nonce);
 
-        let decrypted_data = cipher
-            .decrypt(nonce, encrypted_mnemonic.encrypted_data.as_ref())
-            .map_err(|e| anyhow!("Decryption failed: {}", e))?;
+    let decrypted_data = cipher
+        .decrypt(nonce, encrypted_mnemonic.encrypted_data.as_ref())
+        .map_err(|e| anyhow!("Decryption failed: {}", e))?;
 
-        String::from_utf8(decrypted_data).map_err(|e| anyhow!("Invalid UTF-8: {}", e))
+    if encrypted_mnemonic.version < 2 {
+        let mnemonic =
+            String::from_utf8(decrypted_data).map_err(|e| anyhow!("Invalid UTF-8: {}", e))?;
+        return Ok(Seed {
+            mnemonic,
+            passphrase: None,
+        });
     }
+    Ok(serde_json::from_slice(&decrypted_data)?)
+}
+
+/// Write to a temporary file next to `path` and rename it over `path`,
+/// so a crash never leaves a truncated mnemonic behind
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
 }
 
-fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32]> {
+fn derive_key(password: &str, salt: &[u8], kdf: &KdfParams) -> Result<[u8; 32]> {
+    let params = argon2::Params::new(kdf.memory, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|e| anyhow!("Invalid KDF parameters: {}", e))?;
     let mut key = [0u8; 32];
-    argon2::Argon2::default()
+    argon2::Argon2::new(kdf.variant.into(), argon2::Version::V0x13, params)
         .hash_password_into(password.as_bytes(), salt, &mut key)
         .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
     Ok(key)
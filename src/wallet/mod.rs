@@ -1,4 +1,13 @@
 pub mod bitserv;
 pub mod client;
-mod mnemonic;
-mod paths;
+pub mod coins;
+pub mod customers;
+pub mod deposits;
+pub mod invoices;
+pub mod ledger;
+pub mod mnemonic;
+pub mod notifier;
+pub mod paths;
+pub mod sync;
+pub mod transactions;
+pub mod withdrawals;
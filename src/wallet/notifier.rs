@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use zmq::{Context, Socket};
+
+/// bitcoind topics that should wake up the sync thread
+const TOPICS: [&str; 2] = ["hashblock", "rawtx"];
+
+/// Subscriber for bitcoind's `zmqpubhashblock`/`zmqpubrawtx` notifications
+pub struct BlockNotifier {
+    socket: Socket,
+}
+
+impl BlockNotifier {
+    pub fn new(endpoint: &str) -> Result<Self> {
+        let context = Context::new();
+        let socket = context.socket(zmq::SUB)?;
+        socket.connect(endpoint)?;
+        for topic in TOPICS {
+            socket.set_subscribe(topic.as_bytes())?;
+        }
+        Ok(Self { socket })
+    }
+
+    /// Block until bitcoind announces a new block or transaction, or `timeout` passes.
+    /// Returns `true` if a notification arrived.
+    pub fn wait(&self, timeout: Duration) -> Result<bool> {
+        let ready = self.socket.poll(zmq::POLLIN, timeout.as_millis() as i64)?;
+        if ready == 0 {
+            return Ok(false);
+        }
+
+        // Drain everything that queued up, a single sync covers all of it
+        loop {
+            match self.socket.recv_multipart(zmq::DONTWAIT) {
+                Ok(parts) => {
+                    if let Some(topic) = parts.first() {
+                        println!("Received {} notification", String::from_utf8_lossy(topic));
+                    }
+                }
+                Err(zmq::Error::EAGAIN) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(true)
+    }
+}
@@ -1,28 +1,83 @@
+use anyhow::{anyhow, Result};
 use sha2::{Digest, Sha256};
-use std::path::PathBuf;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug)]
+/// Directory the files were written to before wallets were named explicitly
+pub const LEGACY_DIR: &str = "pers";
+
+/// Files of a wallet, stored under `<data_dir>/<wallet_name>/`
+#[derive(Debug, Clone)]
 pub struct WalletPaths {
+    pub wallet_dir: PathBuf,
     pub mnemonic_path: PathBuf,
     pub wallet_path: PathBuf,
 }
 
 impl WalletPaths {
-    pub fn from_password(password: &str) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(password.as_bytes());
-        let result = hasher.finalize();
-        let hash = hex::encode(&result[..16]); // Use first 16 bytes for shorter filename
+    pub fn new(data_dir: impl AsRef<Path>, wallet_name: &str) -> Result<Self> {
+        if wallet_name.is_empty()
+            || wallet_name == "."
+            || wallet_name == ".."
+            || wallet_name.contains(['/', '\\'])
+        {
+            return Err(anyhow!("Invalid wallet name: {:?}", wallet_name));
+        }
+
+        let wallet_dir = data_dir.as_ref().join(wallet_name);
+        fs::create_dir_all(&wallet_dir)?;
 
-        let pers_dir = PathBuf::from("pers");
-        std::fs::create_dir_all(&pers_dir).expect("Failed to create persistence directory");
+        Ok(Self {
+            mnemonic_path: wallet_dir.join("mnemonic.dat"),
+            wallet_path: wallet_dir.join("wallet.sqlite"),
+            wallet_dir,
+        })
+    }
+
+    /// Move the files of a wallet from the legacy layout, where they were named after a
+    /// hash of the password, into this wallet's directory. Returns whether anything moved.
+    /// Does nothing once the wallet has a mnemonic of its own.
+    pub fn migrate_legacy(&self, legacy_dir: impl AsRef<Path>, password: &str) -> Result<bool> {
+        if self.mnemonic_path.exists() {
+            return Ok(false);
+        }
 
-        let mnemonic_path = pers_dir.join(format!("mnemonic_{}.dat", hash));
-        let wallet_path = pers_dir.join(format!("wallet_{}.sqlite", hash));
+        let hash = legacy_hash(password);
+        let legacy_mnemonic = legacy_dir.as_ref().join(format!("mnemonic_{}.dat", hash));
+        let legacy_wallet = legacy_dir.as_ref().join(format!("wallet_{}.sqlite", hash));
+        if !legacy_mnemonic.exists() {
+            return Ok(false);
+        }
 
-        Self {
-            mnemonic_path,
-            wallet_path,
+        // Move the sqlite wallet first, so an interrupted migration is retried
+        // on the next start instead of leaving the mnemonic without its wallet
+        if legacy_wallet.exists() {
+            move_file(&legacy_wallet, &self.wallet_path)?;
         }
+        move_file(&legacy_mnemonic, &self.mnemonic_path)?;
+        println!(
+            "Migrated wallet from {} to {}",
+            legacy_dir.as_ref().display(),
+            self.wallet_dir.display()
+        );
+        Ok(true)
+    }
+}
+
+fn legacy_hash(password: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(password.as_bytes());
+    let result = hasher.finalize();
+    hex::encode(&result[..16])
+}
+
+/// Rename, falling back to copy and delete when the paths are on different filesystems
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
     }
+    Ok(())
 }
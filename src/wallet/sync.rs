@@ -0,0 +1,512 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use bdk_bitcoind_rpc::{bitcoincore_rpc, Emitter};
+use bdk_electrum::{electrum_client, BdkElectrumClient};
+use bdk_esplora::{esplora_client, EsploraExt};
+use bdk_wallet::{
+    bitcoin::{self, Address, Amount, Block, ScriptBuf, Transaction, Txid},
+    chain::{BlockId, ChainPosition, CheckPoint},
+    rusqlite::Connection,
+    PersistedWallet, Update,
+};
+
+use super::{
+    customers::CustomerRegistry,
+    deposits::DepositTracker,
+    invoices::{Invoice, InvoiceBook, InvoiceStatus},
+    ledger::Ledger,
+};
+use crate::pubsub::{ChainEvent, EventBus};
+
+/// Number of consecutive unused scripts after which a full scan stops
+pub const STOP_GAP: usize = 20;
+/// Number of scripts sent to the Electrum server per request
+pub const BATCH_SIZE: usize = 5;
+/// Number of concurrent requests made to the Esplora API
+pub const PARALLEL_REQUESTS: usize = 5;
+
+/// Shared wallet handles used by the background sync thread
+pub struct SyncWorker {
+    pub wallet: Arc<Mutex<PersistedWallet<Connection>>>,
+    pub conn: Arc<Mutex<Connection>>,
+    pub publisher: Arc<Mutex<EventBus>>,
+    pub deposits: DepositTracker,
+    pub customers: CustomerRegistry,
+    pub ledger: Ledger,
+    pub invoices: InvoiceBook,
+    pub confirmation_threshold: u32,
+}
+
+impl SyncWorker {
+    /// Emitter starting at the wallet tip. Keep it across syncs: it remembers which
+    /// mempool transactions it already emitted and only fetches newer ones.
+    pub fn rpc_emitter<'c>(
+        &self,
+        rpc_client: &'c bitcoincore_rpc::Client,
+    ) -> Emitter<'c, bitcoincore_rpc::Client> {
+        let wallet_tip: CheckPoint = self.wallet.lock().unwrap().latest_checkpoint();
+        Emitter::new(rpc_client, wallet_tip.clone(), wallet_tip.height())
+    }
+
+    /// Apply every block the node has past the emitter's tip, publishing deposits per block
+    pub fn sync_rpc(&self, emitter: &mut Emitter<bitcoincore_rpc::Client>) -> Result<()> {
+        while let Some(block_event) = emitter.next_block()? {
+            self.apply_block(
+                &block_event.block,
+                block_event.block_height(),
+                block_event.connected_to(),
+            )?;
+        }
+
+        let mempool_emissions: Vec<(Transaction, u64)> = emitter.mempool()?;
+        self.apply_mempool(mempool_emissions)
+    }
+
+    /// Apply a block connected to `connected_to`, publishing the deposits it confirms
+    /// and the ones it reverts when it replaces blocks we already applied
+    pub fn apply_block(&self, block: &Block, height: u32, connected_to: BlockId) -> Result<()> {
+        print!("Processing block {} ", height);
+
+        // First pass: collect transaction details
+        let tx_details = {
+            let wallet = self.wallet.lock().unwrap();
+            collect_deposits(&wallet, &block.txdata)
+        }; // wallet lock released here
+
+        // Second pass: apply block
+        let reverted_details = {
+            let mut wallet = self.wallet.lock().unwrap();
+            let previous_tip = wallet.latest_checkpoint();
+            // A block at or below our tip replaces blocks we already applied
+            let confirmed_before = if height <= previous_tip.height() {
+                confirmed_txids(&wallet)
+            } else {
+                HashSet::new()
+            };
+
+            wallet.apply_block_connected_to(block, height, connected_to)?;
+            wallet.persist(&mut self.conn.lock().unwrap())?;
+            println!("Block applied to wallet");
+
+            reverted_deposits(&wallet, &previous_tip, &confirmed_before)
+        }; // wallet lock released here
+
+        self.publish_reverted_deposits(reverted_details);
+        self.publish_deposits(tx_details);
+
+        println!("✓"); // Visual indicator of block completion
+        Ok(())
+    }
+
+    /// Apply unconfirmed transactions from the node's mempool, publishing pending
+    /// deposits for the ones the wallet hasn't seen before
+    pub fn apply_mempool(&self, mempool_emissions: Vec<(Transaction, u64)>) -> Result<()> {
+        let tx_details = {
+            let mut wallet = self.wallet.lock().unwrap();
+            // The first poll of an emitter hands back the whole mempool, and later ones
+            // can repeat transactions, so only those missing from the tx graph are new
+            let new_txs: Vec<&Transaction> = mempool_emissions
+                .iter()
+                .map(|(tx, _)| tx)
+                .filter(|tx| wallet.tx_graph().get_tx(tx.compute_txid()).is_none())
+                .collect();
+            let tx_details = collect_deposits(&wallet, new_txs);
+
+            wallet.apply_unconfirmed_txs(mempool_emissions);
+            wallet.persist(&mut self.conn.lock().unwrap())?;
+            tx_details
+        }; // wallet lock released here
+
+        self.publish_pending_deposits(tx_details);
+
+        Ok(())
+    }
+
+    /// Sync all revealed scripts against the Electrum server, publishing deposits
+    /// for transactions that confirmed since the last sync
+    pub fn sync_electrum(
+        &self,
+        electrum_client: &BdkElectrumClient<electrum_client::Client>,
+    ) -> Result<()> {
+        let request = self
+            .wallet
+            .lock()
+            .unwrap()
+            .start_sync_with_revealed_spks()
+            .build();
+        let update = electrum_client.sync(request, BATCH_SIZE, true)?;
+        self.apply_spk_update(update)
+    }
+
+    /// Sync all revealed scripts against the Esplora API, publishing deposits
+    /// for transactions that confirmed since the last sync
+    pub fn sync_esplora(&self, esplora_client: &esplora_client::BlockingClient) -> Result<()> {
+        let request = self
+            .wallet
+            .lock()
+            .unwrap()
+            .start_sync_with_revealed_spks()
+            .build();
+        let update = esplora_client.sync(request, PARALLEL_REQUESTS)?;
+        self.apply_spk_update(update)
+    }
+
+    /// Apply an update from a script-based backend and publish newly confirmed
+    /// and newly seen unconfirmed deposits
+    pub fn apply_spk_update(&self, update: impl Into<Update>) -> Result<()> {
+        let (tx_details, pending_details, reverted_details) = {
+            let mut wallet = self.wallet.lock().unwrap();
+            let known_before: HashSet<Txid> = wallet
+                .tx_graph()
+                .full_txs()
+                .map(|tx_node| tx_node.txid)
+                .collect();
+            let confirmed_before = confirmed_txids(&wallet);
+            let previous_tip = wallet.latest_checkpoint();
+            wallet.apply_update(update)?;
+            wallet.persist(&mut self.conn.lock().unwrap())?;
+            let reverted_details = reverted_deposits(&wallet, &previous_tip, &confirmed_before);
+
+            let mut newly_confirmed = Vec::<Arc<Transaction>>::new();
+            let mut newly_pending = Vec::<Arc<Transaction>>::new();
+            for wallet_tx in wallet.transactions() {
+                if wallet_tx.chain_position.is_confirmed() {
+                    if !confirmed_before.contains(&wallet_tx.tx_node.txid) {
+                        newly_confirmed.push(wallet_tx.tx_node.tx.clone());
+                    }
+                } else if !known_before.contains(&wallet_tx.tx_node.txid) {
+                    newly_pending.push(wallet_tx.tx_node.tx.clone());
+                }
+            }
+            (
+                collect_deposits(&wallet, newly_confirmed.iter().map(|tx| tx.as_ref())),
+                collect_deposits(&wallet, newly_pending.iter().map(|tx| tx.as_ref())),
+                reverted_details,
+            )
+        }; // wallet lock released here
+
+        self.publish_reverted_deposits(reverted_details);
+        self.publish_pending_deposits(pending_details);
+        self.publish_deposits(tx_details);
+
+        Ok(())
+    }
+
+    /// Publish a confirmation update for every incoming deposit whose confirmation
+    /// count grew, up to the threshold, and credit the ones that reached it
+    pub fn update_confirmations(&self) -> Result<()> {
+        let credited = self.deposits.credited_txids()?;
+        let mut events = Vec::new();
+        let mut newly_credited = Vec::new();
+
+        {
+            let wallet = self.wallet.lock().unwrap();
+            let tip_height = wallet.latest_checkpoint().height();
+
+            for wallet_tx in wallet.transactions() {
+                let txid = wallet_tx.tx_node.txid.to_string();
+                if credited.contains(&txid) {
+                    continue;
+                }
+
+                // Only track transactions that pay us without spending our coins
+                let (sent, received) = wallet.sent_and_received(&wallet_tx.tx_node.tx);
+                if sent > Amount::ZERO || received == Amount::ZERO {
+                    continue;
+                }
+
+                let confirmations = match &wallet_tx.chain_position {
+                    ChainPosition::Confirmed { anchor, .. } => {
+                        tip_height.saturating_sub(anchor.block_id.height) + 1
+                    }
+                    ChainPosition::Unconfirmed { .. } => 0,
+                };
+                let capped = confirmations.min(self.confirmation_threshold);
+                let previous = self
+                    .deposits
+                    .get(&txid)?
+                    .map(|deposit| deposit.confirmations)
+                    .unwrap_or(0);
+
+                if capped != previous {
+                    self.deposits
+                        .set_confirmations(&txid, received.to_sat(), capped)?;
+                }
+                // One update per count, even when several blocks arrived since the last sync
+                for count in previous + 1..=capped {
+                    events.push(ChainEvent::NewTransaction {
+                        txid: txid.clone(),
+                        amount: received.to_sat() as i64,
+                        confirmations: count,
+                    });
+                }
+                if confirmations >= self.confirmation_threshold {
+                    let details = collect_deposits(&wallet, [&*wallet_tx.tx_node.tx]);
+                    newly_credited.push((txid, details));
+                }
+            }
+        } // wallet lock released here
+
+        // Credit customers before marking the deposit, so a failure is retried next sync
+        let mut credited_details = Vec::new();
+        for (txid, details) in newly_credited {
+            self.credit_customers(&txid, &details)?;
+            self.deposits.mark_credited(&txid)?;
+            credited_details.extend(details);
+        }
+
+        for event in events {
+            if let Err(e) = self.publisher.lock().unwrap().publish(event) {
+                eprintln!("Failed to publish confirmation update: {}", e);
+            }
+        }
+
+        if !credited_details.is_empty() {
+            if let Err(e) = self
+                .publisher
+                .lock()
+                .unwrap()
+                .publish(ChainEvent::CreditedDeposits {
+                    deposits: credited_details,
+                })
+            {
+                eprintln!("Failed to publish credited deposits: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Match everything paid to open invoices' addresses against the expected amount,
+    /// publishing every change. Mempool payments count towards the status, like pending
+    /// deposits do, but an invoice stays open until its payment reaches the threshold.
+    pub fn update_invoices(&self) -> Result<()> {
+        let open = self.invoices.list_open()?;
+        if open.is_empty() {
+            return Ok(());
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        // (received, confirmed) per invoice script
+        let received = {
+            let wallet = self.wallet.lock().unwrap();
+            let tip_height = wallet.latest_checkpoint().height();
+            let mut received: HashMap<ScriptBuf, (u64, u64)> = HashMap::new();
+            for invoice in &open {
+                let address = Address::from_str(&invoice.address)?.assume_checked();
+                received.insert(address.script_pubkey(), (0, 0));
+            }
+            // Only canonical transactions are listed, so a double-spent payment drops out
+            for wallet_tx in wallet.transactions() {
+                let confirmations = match &wallet_tx.chain_position {
+                    ChainPosition::Confirmed { anchor, .. } => {
+                        tip_height.saturating_sub(anchor.block_id.height) + 1
+                    }
+                    ChainPosition::Unconfirmed { .. } => 0,
+                };
+                for out in &wallet_tx.tx_node.tx.output {
+                    if let Some((total, confirmed)) = received.get_mut(&out.script_pubkey) {
+                        *total += out.value.to_sat();
+                        if confirmations >= self.confirmation_threshold {
+                            *confirmed += out.value.to_sat();
+                        }
+                    }
+                }
+            }
+            received
+        }; // wallet lock released here
+
+        for invoice in open {
+            let script = Address::from_str(&invoice.address)?
+                .assume_checked()
+                .script_pubkey();
+            let (paid, confirmed) = received.get(&script).copied().unwrap_or((0, 0));
+            let status = InvoiceStatus::evaluate(invoice.amount, paid, now >= invoice.expires_at);
+            if status == invoice.status
+                && paid == invoice.received
+                && confirmed == invoice.confirmed
+            {
+                continue;
+            }
+
+            self.invoices.update(invoice.id, paid, confirmed, status)?;
+            self.publish_invoice(Invoice {
+                received: paid,
+                confirmed,
+                status,
+                ..invoice
+            });
+        }
+
+        Ok(())
+    }
+
+    fn publish_invoice(&self, invoice: Invoice) {
+        if let Err(e) = self.publisher.lock().unwrap().publish(ChainEvent::Invoice {
+            id: invoice.id,
+            address: invoice.address,
+            amount: invoice.amount,
+            received: invoice.received,
+            confirmed: invoice.confirmed,
+            status: invoice.status,
+        }) {
+            eprintln!("Failed to publish invoice {}: {}", invoice.id, e);
+        }
+    }
+
+    /// Post a ledger credit for every customer the deposit paid
+    fn credit_customers(&self, txid: &str, tx_details: &[(String, u64, String)]) -> Result<()> {
+        let customers = self
+            .customers
+            .customers_of(tx_details.iter().map(|(address, _, _)| address.as_str()))?;
+
+        let mut credits: BTreeMap<String, u64> = BTreeMap::new();
+        for (address, amount, _) in tx_details {
+            if let Some(customer_id) = customers.get(address) {
+                *credits.entry(customer_id.clone()).or_default() += amount;
+            }
+        }
+        self.ledger
+            .credit_deposit(txid, &credits.into_iter().collect::<Vec<_>>())
+    }
+
+    fn publish_deposits(&self, tx_details: Vec<(String, u64, String)>) {
+        // Only publish if we found any deposits
+        if tx_details.is_empty() {
+            return;
+        }
+        let customers = self
+            .customers
+            .customers_of(tx_details.iter().map(|(address, _, _)| address.as_str()))
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to look up deposit customers: {}", e);
+                Default::default()
+            });
+        if let Err(e) = self
+            .publisher
+            .lock()
+            .unwrap()
+            .publish(ChainEvent::NewDeposits {
+                deposits: tx_details,
+                customers,
+            })
+        {
+            eprintln!("Failed to publish block details: {}", e);
+        }
+    }
+
+    fn publish_reverted_deposits(&self, tx_details: Vec<(String, u64, String)>) {
+        if tx_details.is_empty() {
+            return;
+        }
+
+        // Reverted deposits go back to being tracked from zero confirmations
+        let txids: HashSet<&str> = tx_details
+            .iter()
+            .map(|(_, _, txid)| txid.as_str())
+            .collect();
+        for txid in txids {
+            if let Err(e) = self.deposits.reset(txid) {
+                eprintln!("Failed to reset deposit {}: {}", txid, e);
+            }
+            if let Err(e) = self.ledger.reverse_deposit(txid) {
+                eprintln!("Failed to reverse ledger credit of deposit {}: {}", txid, e);
+            }
+        }
+
+        if let Err(e) = self
+            .publisher
+            .lock()
+            .unwrap()
+            .publish(ChainEvent::RevertedDeposits {
+                deposits: tx_details,
+            })
+        {
+            eprintln!("Failed to publish reverted deposits: {}", e);
+        }
+    }
+
+    fn publish_pending_deposits(&self, tx_details: Vec<(String, u64, String)>) {
+        if tx_details.is_empty() {
+            return;
+        }
+        if let Err(e) = self
+            .publisher
+            .lock()
+            .unwrap()
+            .publish(ChainEvent::PendingDeposits {
+                deposits: tx_details,
+            })
+        {
+            eprintln!("Failed to publish mempool details: {}", e);
+        }
+    }
+}
+
+/// Collect every wallet output in `txs` as (address, amount, txid)
+pub fn collect_deposits<'a>(
+    wallet: &PersistedWallet<Connection>,
+    txs: impl IntoIterator<Item = &'a Transaction>,
+) -> Vec<(String, u64, String)> {
+    let mut tx_details = Vec::new();
+    for tx in txs {
+        for out in &tx.output {
+            if wallet.is_mine(out.script_pubkey.clone()) {
+                if let Ok(address) =
+                    bitcoin::Address::from_script(&out.script_pubkey, wallet.network())
+                {
+                    tx_details.push((
+                        address.to_string(),
+                        out.value.to_sat(),
+                        tx.compute_txid().to_string(),
+                    ));
+                }
+            }
+        }
+    }
+    tx_details
+}
+
+fn confirmed_txids(wallet: &PersistedWallet<Connection>) -> HashSet<Txid> {
+    wallet
+        .transactions()
+        .filter(|wallet_tx| wallet_tx.chain_position.is_confirmed())
+        .map(|wallet_tx| wallet_tx.tx_node.txid)
+        .collect()
+}
+
+/// Deposits that were confirmed before `previous_tip` got disconnected by a reorg
+/// and are no longer confirmed in the wallet's chain
+fn reverted_deposits(
+    wallet: &PersistedWallet<Connection>,
+    previous_tip: &CheckPoint,
+    confirmed_before: &HashSet<Txid>,
+) -> Vec<(String, u64, String)> {
+    // The previous tip is still part of our chain, nothing was disconnected
+    if wallet
+        .local_chain()
+        .get(previous_tip.height())
+        .is_some_and(|cp| cp.hash() == previous_tip.hash())
+    {
+        return Vec::new();
+    }
+    println!(
+        "Reorg detected, block {} at height {} was disconnected",
+        previous_tip.hash(),
+        previous_tip.height()
+    );
+
+    let confirmed_after = confirmed_txids(wallet);
+    let reverted: Vec<Arc<Transaction>> = confirmed_before
+        .iter()
+        .filter(|txid| !confirmed_after.contains(*txid))
+        .filter_map(|txid| wallet.tx_graph().get_tx(*txid))
+        .collect();
+    collect_deposits(wallet, reverted.iter().map(|tx| tx.as_ref()))
+}
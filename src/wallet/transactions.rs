@@ -0,0 +1,273 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use bdk_wallet::{
+    bitcoin::{consensus::encode::serialize_hex, Address, ScriptBuf, Transaction, Txid},
+    chain::ChainPosition,
+    rusqlite::Connection,
+    KeychainKind, PersistedWallet, WalletTx,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Pays us without spending our coins
+    Incoming,
+    /// Spends our coins to someone else
+    Outgoing,
+    /// Spends our coins back to ourselves, e.g. consolidation
+    #[serde(rename = "self")]
+    SelfTransfer,
+}
+
+impl FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "incoming" => Ok(Direction::Incoming),
+            "outgoing" => Ok(Direction::Outgoing),
+            "self" => Ok(Direction::SelfTransfer),
+            _ => Err(anyhow!("Unknown direction: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TransactionSummary {
+    pub txid: String,
+    pub direction: Direction,
+    pub amount: i64,      // net effect on the wallet in sats
+    pub fee: Option<u64>, // in sats, unknown if we don't own all inputs
+    pub confirmation_height: Option<u32>,
+    pub confirmation_time: Option<u64>, // unix timestamp of the confirming block
+}
+
+/// Where one of our scripts was derived from
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Derivation {
+    pub keychain: KeychainKind,
+    pub index: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InputDetail {
+    pub previous_output: String, // txid:vout
+    pub address: Option<String>, // unknown if the previous transaction isn't ours
+    pub amount: Option<u64>,     // in sats
+    pub derivation: Option<Derivation>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OutputDetail {
+    pub vout: u32,
+    pub address: Option<String>, // none for non-standard scripts such as OP_RETURN
+    pub amount: u64,             // in sats
+    pub derivation: Option<Derivation>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TransactionDetail {
+    pub txid: String,
+    pub direction: Direction,
+    pub sent: u64,     // from our inputs, in sats
+    pub received: u64, // to our outputs, in sats
+    pub fee: Option<u64>,
+    pub fee_rate: Option<f64>, // in sat/vB
+    pub confirmations: u32,
+    pub confirmation_height: Option<u32>,
+    pub confirmation_time: Option<u64>,
+    pub last_seen: Option<u64>, // last time an unconfirmed transaction was seen in the mempool
+    pub inputs: Vec<InputDetail>,
+    pub outputs: Vec<OutputDetail>,
+    pub hex: String,
+}
+
+/// Which wallet transactions to list. Unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionFilter {
+    pub direction: Option<Direction>,
+    pub confirmed: Option<bool>,
+    pub min_height: Option<u32>,
+    pub max_height: Option<u32>,
+    /// Only transactions paying to or spending from this script
+    pub script: Option<ScriptBuf>,
+}
+
+/// Wallet transactions matching `filter`, unconfirmed first and then newest first
+pub fn list_transactions(
+    wallet: &PersistedWallet<Connection>,
+    filter: &TransactionFilter,
+    offset: usize,
+    limit: usize,
+) -> Vec<TransactionSummary> {
+    let mut transactions: Vec<_> = wallet
+        .transactions()
+        .filter(|wallet_tx| matches(wallet, wallet_tx, filter))
+        .map(|wallet_tx| summarize(wallet, &wallet_tx))
+        .collect();
+
+    transactions.sort_by(|a, b| {
+        let height = |tx: &TransactionSummary| tx.confirmation_height.unwrap_or(u32::MAX);
+        height(b).cmp(&height(a)).then_with(|| a.txid.cmp(&b.txid))
+    });
+
+    transactions.into_iter().skip(offset).take(limit).collect()
+}
+
+pub fn summarize(wallet: &PersistedWallet<Connection>, wallet_tx: &WalletTx) -> TransactionSummary {
+    let tx = &wallet_tx.tx_node.tx;
+    let (sent, received) = wallet.sent_and_received(tx);
+    let (confirmation_height, confirmation_time) = match &wallet_tx.chain_position {
+        ChainPosition::Confirmed { anchor, .. } => {
+            (Some(anchor.block_id.height), Some(anchor.confirmation_time))
+        }
+        ChainPosition::Unconfirmed { .. } => (None, None),
+    };
+
+    TransactionSummary {
+        txid: wallet_tx.tx_node.txid.to_string(),
+        direction: direction(wallet, tx),
+        amount: received.to_sat() as i64 - sent.to_sat() as i64,
+        fee: wallet.calculate_fee(tx).ok().map(|fee| fee.to_sat()),
+        confirmation_height,
+        confirmation_time,
+    }
+}
+
+fn direction(wallet: &PersistedWallet<Connection>, tx: &Transaction) -> Direction {
+    let (sent, _) = wallet.sent_and_received(tx);
+    if sent.to_sat() == 0 {
+        Direction::Incoming
+    } else if tx
+        .output
+        .iter()
+        .all(|out| wallet.is_mine(out.script_pubkey.clone()))
+    {
+        Direction::SelfTransfer
+    } else {
+        Direction::Outgoing
+    }
+}
+
+fn matches(
+    wallet: &PersistedWallet<Connection>,
+    wallet_tx: &WalletTx,
+    filter: &TransactionFilter,
+) -> bool {
+    let tx = &wallet_tx.tx_node.tx;
+    if let Some(direction) = filter.direction {
+        if self::direction(wallet, tx) != direction {
+            return false;
+        }
+    }
+
+    let height = match &wallet_tx.chain_position {
+        ChainPosition::Confirmed { anchor, .. } => Some(anchor.block_id.height),
+        ChainPosition::Unconfirmed { .. } => None,
+    };
+    if let Some(confirmed) = filter.confirmed {
+        if height.is_some() != confirmed {
+            return false;
+        }
+    }
+    // Height bounds only match confirmed transactions
+    if filter
+        .min_height
+        .is_some_and(|min| height.is_none_or(|h| h < min))
+        || filter
+            .max_height
+            .is_some_and(|max| height.is_none_or(|h| h > max))
+    {
+        return false;
+    }
+
+    match &filter.script {
+        Some(script) => {
+            tx.output.iter().any(|out| &out.script_pubkey == script)
+                || tx.input.iter().any(|input| {
+                    wallet
+                        .tx_graph()
+                        .get_txout(input.previous_output)
+                        .is_some_and(|prevout| &prevout.script_pubkey == script)
+                })
+        }
+        None => true,
+    }
+}
+
+/// Decoded wallet transaction, marking which inputs and outputs are ours
+pub fn transaction_detail(
+    wallet: &PersistedWallet<Connection>,
+    txid: Txid,
+) -> Option<TransactionDetail> {
+    let wallet_tx = wallet.get_tx(txid)?;
+    let tx = &wallet_tx.tx_node.tx;
+    let network = wallet.network();
+    let address = |script: &ScriptBuf| {
+        Address::from_script(script, network)
+            .ok()
+            .map(|address| address.to_string())
+    };
+    let derivation = |script: &ScriptBuf| {
+        wallet
+            .derivation_of_spk(script.clone())
+            .map(|(keychain, index)| Derivation { keychain, index })
+    };
+
+    let inputs = tx
+        .input
+        .iter()
+        .map(|input| {
+            let prevout = wallet.tx_graph().get_txout(input.previous_output);
+            InputDetail {
+                previous_output: input.previous_output.to_string(),
+                address: prevout.and_then(|prevout| address(&prevout.script_pubkey)),
+                amount: prevout.map(|prevout| prevout.value.to_sat()),
+                derivation: prevout.and_then(|prevout| derivation(&prevout.script_pubkey)),
+            }
+        })
+        .collect();
+    let outputs = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(vout, out)| OutputDetail {
+            vout: vout as u32,
+            address: address(&out.script_pubkey),
+            amount: out.value.to_sat(),
+            derivation: derivation(&out.script_pubkey),
+        })
+        .collect();
+
+    let (sent, received) = wallet.sent_and_received(tx);
+    let fee = wallet.calculate_fee(tx).ok().map(|fee| fee.to_sat());
+    let (confirmation_height, confirmation_time, last_seen) = match &wallet_tx.chain_position {
+        ChainPosition::Confirmed { anchor, .. } => (
+            Some(anchor.block_id.height),
+            Some(anchor.confirmation_time),
+            None,
+        ),
+        ChainPosition::Unconfirmed { last_seen } => (None, None, *last_seen),
+    };
+    let tip_height = wallet.latest_checkpoint().height();
+
+    Some(TransactionDetail {
+        txid: txid.to_string(),
+        direction: direction(wallet, tx),
+        sent: sent.to_sat(),
+        received: received.to_sat(),
+        fee,
+        fee_rate: fee.map(|fee| fee as f64 / tx.vsize() as f64),
+        confirmations: confirmation_height
+            .map(|height| tip_height.saturating_sub(height) + 1)
+            .unwrap_or(0),
+        confirmation_height,
+        confirmation_time,
+        last_seen,
+        inputs,
+        outputs,
+        hex: serialize_hex(tx.as_ref()),
+    })
+}
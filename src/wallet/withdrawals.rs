@@ -0,0 +1,236 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use bdk_wallet::rusqlite::{self, params, types::Type, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::ledger::{self, Ledger};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WithdrawalStatus {
+    Pending,
+    Broadcast,
+    Confirmed,
+    /// Taken out of the queue before it was paid, and refunded if it was debited
+    Cancelled,
+}
+
+impl WithdrawalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WithdrawalStatus::Pending => "pending",
+            WithdrawalStatus::Broadcast => "broadcast",
+            WithdrawalStatus::Confirmed => "confirmed",
+            WithdrawalStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for WithdrawalStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(WithdrawalStatus::Pending),
+            "broadcast" => Ok(WithdrawalStatus::Broadcast),
+            "confirmed" => Ok(WithdrawalStatus::Confirmed),
+            "cancelled" => Ok(WithdrawalStatus::Cancelled),
+            _ => Err(anyhow!("Unknown withdrawal status: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub id: i64,
+    pub address: String,
+    pub amount: u64,
+    pub status: WithdrawalStatus,
+    pub txid: Option<String>,
+}
+
+/// Payout requests waiting to be batched into a single transaction.
+/// Stored in the wallet's SQLite database next to the bdk tables and the ledger,
+/// which customer withdrawals are debited from in the same database transaction.
+#[derive(Clone)]
+pub struct WithdrawalQueue {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl WithdrawalQueue {
+    pub fn new(conn: Arc<Mutex<Connection>>) -> Result<Self> {
+        conn.lock().unwrap().execute(
+            "CREATE TABLE IF NOT EXISTS bitserv_withdrawals (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                address TEXT NOT NULL,
+                amount INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                txid TEXT,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ledger::new(conn.clone())?;
+        Ok(Self { conn })
+    }
+
+    /// Add a payout to the queue, returning its id
+    pub fn enqueue(&self, address: &str, amount: u64) -> Result<i64> {
+        insert(&self.conn.lock().unwrap(), address, amount)
+    }
+
+    /// Add a payout to the queue and debit it from the customer's ledger balance,
+    /// returning its id. Neither happens if the balance doesn't cover it.
+    pub fn enqueue_debited(&self, address: &str, amount: u64, customer_id: &str) -> Result<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        let id = insert(&db_tx, address, amount)?;
+        ledger::debit_withdrawal(&db_tx, customer_id, id, amount)?;
+        db_tx.commit()?;
+        Ok(id)
+    }
+
+    /// Take a payout that hasn't been broadcast out of the queue, refunding the customer
+    /// it was debited from. Returns `None` if there's no such withdrawal.
+    pub fn cancel(&self, id: i64) -> Result<Option<Withdrawal>> {
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        let withdrawal = db_tx
+            .query_row(
+                "SELECT id, address, amount, status, txid FROM bitserv_withdrawals WHERE id = ?1",
+                params![id],
+                row_to_withdrawal,
+            )
+            .optional()?;
+        let Some(mut withdrawal) = withdrawal else {
+            return Ok(None);
+        };
+        if withdrawal.status != WithdrawalStatus::Pending {
+            return Err(anyhow!(
+                "Withdrawal {} is {} and can no longer be cancelled",
+                id,
+                withdrawal.status.as_str()
+            ));
+        }
+
+        db_tx.execute(
+            "UPDATE bitserv_withdrawals SET status = ?1 WHERE id = ?2",
+            params![WithdrawalStatus::Cancelled.as_str(), id],
+        )?;
+        ledger::refund_withdrawal(&db_tx, id)?;
+        db_tx.commit()?;
+
+        withdrawal.status = WithdrawalStatus::Cancelled;
+        Ok(Some(withdrawal))
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<Withdrawal>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, address, amount, status, txid FROM bitserv_withdrawals WHERE id = ?1",
+        )?;
+        let mut rows = stmt.query_map(params![id], row_to_withdrawal)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    pub fn list_by_status(&self, status: WithdrawalStatus) -> Result<Vec<Withdrawal>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, address, amount, status, txid FROM bitserv_withdrawals
+             WHERE status = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![status.as_str()], row_to_withdrawal)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Record that the given payouts were included in the broadcast transaction `txid`.
+    /// Fails without changing any of them unless all are still pending, so a payout
+    /// cancelled while its batch was being built isn't paid after its refund.
+    pub fn mark_broadcast(&self, ids: &[i64], txid: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        for id in ids {
+            let updated = db_tx.execute(
+                "UPDATE bitserv_withdrawals SET status = ?1, txid = ?2
+                 WHERE id = ?3 AND status = ?4",
+                params![
+                    WithdrawalStatus::Broadcast.as_str(),
+                    txid,
+                    id,
+                    WithdrawalStatus::Pending.as_str()
+                ],
+            )?;
+            if updated != 1 {
+                return Err(anyhow!("Withdrawal {} is no longer pending", id));
+            }
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Put payouts back in the queue after their transaction failed to broadcast.
+    /// Only broadcast ones, so a cancelled payout stays cancelled.
+    pub fn mark_pending(&self, ids: &[i64]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        for id in ids {
+            db_tx.execute(
+                "UPDATE bitserv_withdrawals SET status = ?1, txid = NULL
+                 WHERE id = ?2 AND status = ?3",
+                params![
+                    WithdrawalStatus::Pending.as_str(),
+                    id,
+                    WithdrawalStatus::Broadcast.as_str()
+                ],
+            )?;
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    pub fn mark_confirmed(&self, ids: &[i64]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let db_tx = conn.transaction()?;
+        for id in ids {
+            db_tx.execute(
+                "UPDATE bitserv_withdrawals SET status = ?1 WHERE id = ?2",
+                params![WithdrawalStatus::Confirmed.as_str(), id],
+            )?;
+        }
+        db_tx.commit()?;
+        Ok(())
+    }
+}
+
+fn insert(conn: &Connection, address: &str, amount: u64) -> Result<i64> {
+    let created_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    conn.execute(
+        "INSERT INTO bitserv_withdrawals (address, amount, status, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            address,
+            amount,
+            WithdrawalStatus::Pending.as_str(),
+            created_at
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn row_to_withdrawal(row: &Row) -> rusqlite::Result<Withdrawal> {
+    let status: String = row.get(3)?;
+    let status = WithdrawalStatus::from_str(&status)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, e.into()))?;
+    Ok(Withdrawal {
+        id: row.get(0)?,
+        address: row.get(1)?,
+        amount: row.get(2)?,
+        status,
+        txid: row.get(4)?,
+    })
+}
@@ -0,0 +1,35 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::memory_db;
+    use bdk_wallet::bitcoin::OutPoint;
+    use bitserv::FrozenCoins;
+    use std::str::FromStr;
+
+    const OUTPOINT: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b:1";
+
+    fn frozen_coins() -> FrozenCoins {
+        FrozenCoins::new(memory_db()).unwrap()
+    }
+
+    #[test]
+    fn test_freeze_and_unfreeze() {
+        let frozen = frozen_coins();
+        let outpoint = OutPoint::from_str(OUTPOINT).unwrap();
+
+        frozen.freeze(outpoint, Some("suspicious")).unwrap();
+        // Freezing again only updates the reason
+        frozen.freeze(outpoint, Some("under review")).unwrap();
+
+        let list = frozen.list().unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].outpoint, OUTPOINT);
+        assert_eq!(list[0].reason.as_deref(), Some("under review"));
+        assert_eq!(frozen.outpoints().unwrap(), vec![outpoint]);
+
+        assert!(frozen.unfreeze(outpoint).unwrap());
+        assert!(!frozen.unfreeze(outpoint).unwrap());
+        assert!(frozen.outpoints().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+// Helpers shared by the integration tests. Not every test binary uses all of them.
+#![allow(dead_code)]
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use bdk_wallet::{
+    bitcoin::{
+        absolute, block,
+        consensus::encode::deserialize_hex,
+        constants::genesis_block,
+        hashes::{sha256, Hash},
+        transaction, Amount, Block, BlockHash, CompactTarget, Network, OutPoint, ScriptBuf,
+        Transaction, TxIn, TxMerkleNode, TxOut,
+    },
+    chain::BlockId,
+    rusqlite::Connection,
+    KeychainKind, Wallet,
+};
+use bitserv::{
+    pubsub::{EventBus, MemoryPublisher, Outbox},
+    CustomerRegistry, DepositTracker, InvoiceBook, Ledger, SyncWorker,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+const EXTERNAL_DESCRIPTOR: &str = "wpkh(tprv8ZgxMBicQKsPd3krDUsBAmtnRsK3rb8u5yi1zhQgMhF1tR8MW7xfE4rnrbbsrbPR52e7rKapu6ztw1jXveJSCGHEriUGZV7mCe88duLp5pj/84'/1'/0'/0/*)";
+const INTERNAL_DESCRIPTOR: &str = "wpkh(tprv8ZgxMBicQKsPd3krDUsBAmtnRsK3rb8u5yi1zhQgMhF1tR8MW7xfE4rnrbbsrbPR52e7rKapu6ztw1jXveJSCGHEriUGZV7mCe88duLp5pj/84'/1'/0'/1/*)";
+
+/// Empty in-memory SQLite database, shared the way the wallet shares its connection
+pub fn memory_db() -> Arc<Mutex<Connection>> {
+    Arc::new(Mutex::new(Connection::open_in_memory().unwrap()))
+}
+
+/// Sync worker around a fresh regtest wallet in an in-memory database,
+/// publishing to `memory`
+pub fn sync_worker(memory: &MemoryPublisher, confirmation_threshold: u32) -> SyncWorker {
+    let mut conn = Connection::open_in_memory().unwrap();
+    let wallet = Wallet::create(EXTERNAL_DESCRIPTOR, INTERNAL_DESCRIPTOR)
+        .network(Network::Regtest)
+        .create_wallet(&mut conn)
+        .unwrap();
+    let conn = Arc::new(Mutex::new(conn));
+
+    let outbox = Outbox::new(conn.clone()).unwrap();
+    SyncWorker {
+        wallet: Arc::new(Mutex::new(wallet)),
+        publisher: Arc::new(Mutex::new(EventBus::new(Box::new(memory.clone()), outbox))),
+        deposits: DepositTracker::new(conn.clone()).unwrap(),
+        customers: CustomerRegistry::new(conn.clone()).unwrap(),
+        ledger: Ledger::new(conn.clone()).unwrap(),
+        invoices: InvoiceBook::new(conn.clone()).unwrap(),
+        conn,
+        confirmation_threshold,
+    }
+}
+
+/// Script of the worker wallet's external address at `index`
+pub fn wallet_script(worker: &SyncWorker, index: u32) -> ScriptBuf {
+    worker
+        .wallet
+        .lock()
+        .unwrap()
+        .peek_address(KeychainKind::External, index)
+        .script_pubkey()
+}
+
+/// Apply a block with `txs` on top of the block at `parent`, returning the new block.
+/// Vary `nonce` to mine a competing block at the same height.
+pub fn mine(worker: &SyncWorker, parent: BlockId, txs: Vec<Transaction>, nonce: u32) -> BlockId {
+    let block = Block {
+        header: block::Header {
+            version: block::Version::ONE,
+            prev_blockhash: parent.hash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: genesis_block(Network::Regtest).header.time + 600 * (parent.height + 1),
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce,
+        },
+        txdata: txs,
+    };
+    let height = parent.height + 1;
+    worker.apply_block(&block, height, parent).unwrap();
+    BlockId {
+        height,
+        hash: block.block_hash(),
+    }
+}
+
+/// Tip of the worker's wallet chain
+pub fn tip(worker: &SyncWorker) -> BlockId {
+    worker.wallet.lock().unwrap().latest_checkpoint().block_id()
+}
+
+/// Fresh directory under the system temp dir, unique to this test process
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bitserv-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A transaction paying `amount` sats to `script` from an outpoint the wallet doesn't own
+pub fn payment(script: ScriptBuf, amount: u64) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(
+                "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+                    .parse()
+                    .unwrap(),
+                0,
+            ),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(amount),
+            script_pubkey: script,
+        }],
+    }
+}
+
+struct MockBlock {
+    hash: BlockHash,
+    previous: Option<BlockHash>,
+    time: u32,
+}
+
+#[derive(Default)]
+struct MockState {
+    blocks: Vec<MockBlock>,
+    // Esplora transactions by scripthash
+    txs: HashMap<String, Vec<Value>>,
+    broadcast: Vec<Transaction>,
+    reject_broadcasts: bool,
+    lose_broadcast_responses: bool,
+    scripthash_requests: usize,
+}
+
+/// Mock Esplora API serving a regtest chain, transactions confirmed in it
+/// and recording the transactions broadcast to it
+#[derive(Clone)]
+pub struct MockEsplora {
+    pub address: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockEsplora {
+    /// Start serving a chain with only the regtest genesis block
+    pub fn start() -> Self {
+        let genesis = genesis_block(Network::Regtest);
+        let state = Arc::new(Mutex::new(MockState {
+            blocks: vec![MockBlock {
+                hash: genesis.block_hash(),
+                previous: None,
+                time: genesis.header.time,
+            }],
+            ..Default::default()
+        }));
+
+        let app = Router::new()
+            .route("/blocks/tip/height", get(tip_height))
+            .route("/blocks/tip/hash", get(tip_hash))
+            .route("/blocks", get(blocks))
+            .route("/block-height/:height", get(block_hash))
+            .route("/scripthash/:hash/txs", get(scripthash_txs))
+            .route("/tx", post(broadcast))
+            .with_state(state.clone());
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                axum::serve(listener, app).await.unwrap();
+            });
+        });
+
+        Self { address, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Mine a block confirming `tx`, which pays to `script`
+    pub fn confirm(&self, tx: &Transaction, script: &ScriptBuf) {
+        let mut state = self.state.lock().unwrap();
+        let previous = state.blocks.last().unwrap();
+        let height = state.blocks.len() as u32;
+        let block = MockBlock {
+            hash: BlockHash::from_byte_array([height as u8; 32]),
+            previous: Some(previous.hash),
+            time: previous.time + 600,
+        };
+
+        let esplora_tx = json!({
+            "txid": tx.compute_txid(),
+            "version": tx.version.0,
+            "locktime": tx.lock_time.to_consensus_u32(),
+            "vin": tx.input.iter().map(|input| json!({
+                "txid": input.previous_output.txid,
+                "vout": input.previous_output.vout,
+                "prevout": null,
+                "scriptsig": input.script_sig,
+                "witness": [],
+                "sequence": input.sequence.0,
+                "is_coinbase": false,
+            })).collect::<Vec<_>>(),
+            "vout": tx.output.iter().map(|out| json!({
+                "value": out.value.to_sat(),
+                "scriptpubkey": out.script_pubkey,
+            })).collect::<Vec<_>>(),
+            "size": tx.total_size(),
+            "weight": tx.weight().to_wu(),
+            "status": {
+                "confirmed": true,
+                "block_height": height,
+                "block_hash": block.hash,
+                "block_time": block.time,
+            },
+            "fee": 0,
+        });
+        let scripthash = format!("{:x}", sha256::Hash::hash(script.as_bytes()));
+        state.txs.entry(scripthash).or_default().push(esplora_tx);
+        state.blocks.push(block);
+    }
+
+    /// Transactions broadcast so far, oldest first
+    pub fn broadcast(&self) -> Vec<Transaction> {
+        self.state.lock().unwrap().broadcast.clone()
+    }
+
+    /// Make broadcasts fail, like a node rejecting the transaction
+    pub fn reject_broadcasts(&self, reject: bool) {
+        self.state.lock().unwrap().reject_broadcasts = reject;
+    }
+
+    /// Accept broadcasts but answer with a server error, like a proxy timing out
+    /// after the node took the transaction
+    pub fn lose_broadcast_responses(&self, lose: bool) {
+        self.state.lock().unwrap().lose_broadcast_responses = lose;
+    }
+
+    pub fn scripthash_requests(&self) -> usize {
+        self.state.lock().unwrap().scripthash_requests
+    }
+}
+
+async fn tip_height(State(state): State<Arc<Mutex<MockState>>>) -> String {
+    (state.lock().unwrap().blocks.len() - 1).to_string()
+}
+
+async fn tip_hash(State(state): State<Arc<Mutex<MockState>>>) -> String {
+    state
+        .lock()
+        .unwrap()
+        .blocks
+        .last()
+        .unwrap()
+        .hash
+        .to_string()
+}
+
+async fn blocks(State(state): State<Arc<Mutex<MockState>>>) -> Json<Value> {
+    let state = state.lock().unwrap();
+    let blocks: Vec<_> = state
+        .blocks
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(height, block)| {
+            json!({
+                "id": block.hash,
+                "height": height,
+                "timestamp": block.time,
+                "previousblockhash": block.previous,
+                "merkle_root": genesis_block(Network::Regtest).header.merkle_root,
+            })
+        })
+        .collect();
+    Json(json!(blocks))
+}
+
+async fn block_hash(
+    State(state): State<Arc<Mutex<MockState>>>,
+    Path(height): Path<usize>,
+) -> Result<String, StatusCode> {
+    let state = state.lock().unwrap();
+    state
+        .blocks
+        .get(height)
+        .map(|block| block.hash.to_string())
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn scripthash_txs(
+    State(state): State<Arc<Mutex<MockState>>>,
+    Path(hash): Path<String>,
+) -> Json<Value> {
+    let mut state = state.lock().unwrap();
+    state.scripthash_requests += 1;
+    Json(json!(state.txs.get(&hash).cloned().unwrap_or_default()))
+}
+
+async fn broadcast(State(state): State<Arc<Mutex<MockState>>>, body: String) -> StatusCode {
+    let mut state = state.lock().unwrap();
+    match deserialize_hex::<Transaction>(&body) {
+        Ok(_) if state.reject_broadcasts => StatusCode::BAD_REQUEST,
+        Ok(tx) => {
+            state.broadcast.push(tx);
+            if state.lose_broadcast_responses {
+                StatusCode::BAD_GATEWAY
+            } else {
+                StatusCode::OK
+            }
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
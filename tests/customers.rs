@@ -0,0 +1,76 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{memory_db, temp_dir};
+    use bdk_wallet::bitcoin::Network;
+    use bitserv::{pubsub::NoopPublisher, BitServWallet, CustomerRegistry, KdfParams, WalletPaths};
+
+    fn registry() -> CustomerRegistry {
+        CustomerRegistry::new(memory_db()).unwrap()
+    }
+
+    #[test]
+    fn test_latest_address_and_lookup() {
+        let registry = registry();
+        registry.assign("alice", "address1", 0).unwrap();
+        registry.assign("bob", "address2", 1).unwrap();
+        registry.assign("alice", "address3", 2).unwrap();
+
+        let latest = registry.latest_address("alice").unwrap().unwrap();
+        assert_eq!(latest.address, "address3");
+        assert_eq!(latest.index, 2);
+        assert!(registry.latest_address("carol").unwrap().is_none());
+
+        let owner = registry.find_by_address("address2").unwrap().unwrap();
+        assert_eq!(owner.customer_id, "bob");
+        assert!(registry.find_by_address("address4").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_customers_of_skips_unassigned_addresses() {
+        let registry = registry();
+        registry.assign("alice", "address1", 0).unwrap();
+
+        let customers = registry.customers_of(["address1", "address2"]).unwrap();
+        assert_eq!(customers.len(), 1);
+        assert_eq!(customers["address1"], "alice");
+    }
+
+    #[test]
+    fn test_address_can_only_be_assigned_once() {
+        let registry = registry();
+        registry.assign("alice", "address1", 0).unwrap();
+        assert!(registry.assign("bob", "address1", 0).is_err());
+    }
+
+    #[test]
+    fn test_wallet_customer_address() {
+        let wallet = BitServWallet::new(
+            &WalletPaths::new(temp_dir("customers"), "wallet").unwrap(),
+            "your-secure-customers-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(NoopPublisher),
+            Vec::new(),
+        );
+
+        // Stable until a fresh address is requested
+        let first = wallet.customer_address("alice", false).unwrap();
+        let again = wallet.customer_address("alice", false).unwrap();
+        assert_eq!(first, again);
+
+        let fresh = wallet.customer_address("alice", true).unwrap();
+        assert_ne!(fresh.address, first.address);
+        assert_eq!(wallet.customer_address("alice", false).unwrap(), fresh);
+
+        let bob = wallet.customer_address("bob", false).unwrap();
+        assert_ne!(bob.address, fresh.address);
+
+        let owner = wallet.find_customer_by_address(&first.address).unwrap();
+        assert_eq!(owner.unwrap().customer_id, "alice");
+        assert!(wallet.customer_address(" ", false).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::memory_db;
+    use bitserv::{DepositTracker, TrackedDeposit};
+
+    fn create_tracker() -> DepositTracker {
+        DepositTracker::new(memory_db()).expect("Failed to create tracker")
+    }
+
+    #[test]
+    fn test_track_confirmations() {
+        let tracker = create_tracker();
+        assert!(tracker.get("txid1").unwrap().is_none());
+
+        tracker.set_confirmations("txid1", 50000, 1).unwrap();
+        tracker.set_confirmations("txid1", 50000, 2).unwrap();
+
+        assert_eq!(
+            tracker.get("txid1").unwrap(),
+            Some(TrackedDeposit {
+                txid: "txid1".to_string(),
+                amount: 50000,
+                confirmations: 2,
+                credited: false,
+            })
+        );
+        assert!(tracker.credited_txids().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_credit_deposit() {
+        let tracker = create_tracker();
+
+        tracker.set_confirmations("txid1", 50000, 6).unwrap();
+        tracker.set_confirmations("txid2", 75000, 3).unwrap();
+        tracker.mark_credited("txid1").unwrap();
+
+        assert!(tracker.get("txid1").unwrap().unwrap().credited);
+        assert!(!tracker.get("txid2").unwrap().unwrap().credited);
+
+        let credited = tracker.credited_txids().unwrap();
+        assert_eq!(credited.len(), 1);
+        assert!(credited.contains("txid1"));
+    }
+
+    #[test]
+    fn test_reset_reverted_deposit() {
+        let tracker = create_tracker();
+
+        tracker.set_confirmations("txid1", 50000, 6).unwrap();
+        tracker.mark_credited("txid1").unwrap();
+        tracker.reset("txid1").unwrap();
+
+        let deposit = tracker.get("txid1").unwrap().unwrap();
+        assert_eq!(deposit.confirmations, 0);
+        assert!(!deposit.credited);
+        assert!(tracker.credited_txids().unwrap().is_empty());
+    }
+}
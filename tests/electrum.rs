@@ -0,0 +1,73 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::temp_dir;
+    use bdk_wallet::bitcoin::Network;
+    use bitserv::{pubsub::NoopPublisher, BitServWallet, Client, KdfParams, WalletPaths};
+    use lazy_static::lazy_static;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    // Default electrs regtest endpoint
+    const ELECTRUM_URL: &str = "tcp://127.0.0.1:60401";
+
+    struct TestContext {
+        wallet: BitServWallet,
+    }
+
+    impl TestContext {
+        fn new() -> Self {
+            let client = Client::new_electrum(ELECTRUM_URL);
+            let mut wallet = BitServWallet::new(
+                &WalletPaths::new(temp_dir("electrum"), "wallet").unwrap(),
+                "your-secure-electrum-password",
+                None,
+                KdfParams::default(),
+                Network::Regtest,
+                6,
+                Box::new(NoopPublisher),
+                Vec::new(),
+            );
+            wallet.init(&client);
+
+            TestContext { wallet }
+        }
+
+        fn sync_wallet(&mut self) {
+            let client = Client::new_electrum(ELECTRUM_URL);
+            self.wallet.sync(client);
+        }
+    }
+
+    lazy_static! {
+        static ref TEST_CONTEXT: Arc<Mutex<TestContext>> = {
+            let context = TestContext::new();
+            Arc::new(Mutex::new(context))
+        };
+    }
+
+    #[tokio::test]
+    async fn test_electrum_sync() {
+        let mut context = TEST_CONTEXT.lock().await;
+        context.wallet.stop_sync();
+        context.sync_wallet();
+    }
+
+    #[tokio::test]
+    async fn test_electrum_balance_matches_utxos() {
+        let context = TEST_CONTEXT.lock().await;
+
+        let balance = context.wallet.balance();
+        let address_total: f64 = context
+            .wallet
+            .get_addresses_with_balance()
+            .iter()
+            .map(|(_, amount)| amount)
+            .sum();
+        assert!(
+            address_total <= balance.total().to_btc(),
+            "External address balances should not exceed the wallet balance"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{temp_dir, MockEsplora};
+    use bdk_wallet::bitcoin::Network;
+    use bitserv::{pubsub::NoopPublisher, BitServWallet, Client, KdfParams, WalletPaths};
+
+    #[test]
+    fn test_esplora_full_scan() {
+        let esplora = MockEsplora::start();
+
+        let client = Client::new_esplora(&esplora.url());
+        let mut wallet = BitServWallet::new(
+            &WalletPaths::new(temp_dir("esplora"), "wallet").unwrap(),
+            "your-secure-esplora-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(NoopPublisher),
+            Vec::new(),
+        );
+        wallet.init(&client);
+
+        // Both keychains are scanned up to the stop gap
+        assert!(esplora.scripthash_requests() >= 2 * 20);
+        assert_eq!(wallet.balance().total().to_sat(), 0);
+        assert!(wallet.get_all_addresses().is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{memory_db, mine, payment, sync_worker, temp_dir, tip, wallet_script};
+    use bdk_wallet::bitcoin::{Address, Network};
+    use bitserv::{
+        pubsub::{ChainEvent, MemoryPublisher},
+        BitServWallet, InvoiceBook, InvoiceStatus, KdfParams, WalletPaths,
+    };
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn invoice_book() -> InvoiceBook {
+        InvoiceBook::new(memory_db()).unwrap()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn test_evaluate_invoice_status() {
+        assert_eq!(
+            InvoiceStatus::evaluate(1000, 0, false),
+            InvoiceStatus::Pending
+        );
+        assert_eq!(
+            InvoiceStatus::evaluate(1000, 0, true),
+            InvoiceStatus::Expired
+        );
+        assert_eq!(
+            InvoiceStatus::evaluate(1000, 400, false),
+            InvoiceStatus::Underpaid
+        );
+        // A partial payment keeps the invoice open after it expires
+        assert_eq!(
+            InvoiceStatus::evaluate(1000, 400, true),
+            InvoiceStatus::Underpaid
+        );
+        assert_eq!(
+            InvoiceStatus::evaluate(1000, 1000, true),
+            InvoiceStatus::Paid
+        );
+        assert_eq!(
+            InvoiceStatus::evaluate(1000, 1500, false),
+            InvoiceStatus::Overpaid
+        );
+    }
+
+    #[test]
+    fn test_open_invoices() {
+        let invoices = invoice_book();
+        let first = invoices.create("address1", 1000, 100).unwrap();
+        let second = invoices.create("address2", 2000, 100).unwrap();
+        let third = invoices.create("address3", 3000, 100).unwrap();
+        let fourth = invoices.create("address4", 4000, 100).unwrap();
+        // Expired past the grace period
+        let fifth = invoices.create("address5", 5000, 100).unwrap();
+        let sixth = invoices.create("address6", 6000, now() - 60).unwrap();
+
+        invoices
+            .update(first, 1000, 1000, InvoiceStatus::Paid)
+            .unwrap();
+        invoices
+            .update(second, 500, 0, InvoiceStatus::Underpaid)
+            .unwrap();
+        // Paid, but only in the mempool so far
+        invoices
+            .update(fourth, 4000, 0, InvoiceStatus::Paid)
+            .unwrap();
+        invoices
+            .update(fifth, 0, 0, InvoiceStatus::Expired)
+            .unwrap();
+        invoices
+            .update(sixth, 0, 0, InvoiceStatus::Expired)
+            .unwrap();
+
+        let open: Vec<_> = invoices
+            .list_open()
+            .unwrap()
+            .into_iter()
+            .map(|invoice| invoice.id)
+            .collect();
+        assert_eq!(open, vec![second, third, fourth, sixth]);
+
+        let paid = invoices.get(first).unwrap().unwrap();
+        assert_eq!(paid.received, 1000);
+        assert_eq!(paid.confirmed, 1000);
+        assert_eq!(paid.status, InvoiceStatus::Paid);
+        assert!(invoices.get(42).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invoice_uri() {
+        let invoices = invoice_book();
+        let id = invoices.create("bcrt1qexample", 50000, 100).unwrap();
+        let invoice = invoices.get(id).unwrap().unwrap();
+        assert_eq!(invoice.uri(), "bitcoin:bcrt1qexample?amount=0.0005");
+    }
+
+    #[test]
+    fn test_create_invoice_reveals_address() {
+        let memory = MemoryPublisher::new();
+        let wallet = BitServWallet::new(
+            &WalletPaths::new(temp_dir("invoices"), "wallet").unwrap(),
+            "your-secure-invoices-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(memory.clone()),
+            Vec::new(),
+        );
+
+        let invoice = wallet
+            .create_invoice(50000, Duration::from_secs(600))
+            .unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Pending);
+        assert_eq!(invoice.received, 0);
+        assert_eq!(
+            wallet.get_invoice(invoice.id).unwrap(),
+            Some(invoice.clone())
+        );
+
+        match &memory.events()[0].event {
+            ChainEvent::NewAddress { address, .. } => assert_eq!(address, &invoice.address),
+            _ => panic!("Published wrong event type"),
+        }
+
+        assert!(wallet.create_invoice(0, Duration::from_secs(600)).is_err());
+    }
+
+    #[test]
+    fn test_unconfirmed_payment_keeps_invoice_open() {
+        let memory = MemoryPublisher::new();
+        let worker = sync_worker(&memory, 1);
+        let script = wallet_script(&worker, 0);
+        let address = Address::from_script(&script, Network::Regtest).unwrap();
+        let id = worker
+            .invoices
+            .create(&address.to_string(), 50_000, 4_000_000_000)
+            .unwrap();
+
+        worker
+            .apply_mempool(vec![(payment(script.clone(), 50_000), 1)])
+            .unwrap();
+        worker.update_invoices().unwrap();
+        let invoice = worker.invoices.get(id).unwrap().unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Paid);
+        assert_eq!((invoice.received, invoice.confirmed), (50_000, 0));
+        assert_eq!(worker.invoices.list_open().unwrap().len(), 1);
+
+        // The payment is double-spent by a transaction paying elsewhere
+        let conflict = payment(wallet_script(&worker, 1), 40_000);
+        mine(&worker, tip(&worker), vec![conflict], 0);
+        worker.update_invoices().unwrap();
+        let invoice = worker.invoices.get(id).unwrap().unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Pending);
+        assert_eq!((invoice.received, invoice.confirmed), (0, 0));
+
+        // Paid again, and closed once the payment confirms
+        let mut tx = payment(script, 50_000);
+        tx.input[0].previous_output.vout = 1;
+        mine(&worker, tip(&worker), vec![tx], 0);
+        worker.update_invoices().unwrap();
+        let invoice = worker.invoices.get(id).unwrap().unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Paid);
+        assert_eq!((invoice.received, invoice.confirmed), (50_000, 50_000));
+        assert!(worker.invoices.list_open().unwrap().is_empty());
+
+        let statuses: Vec<_> = memory
+            .events()
+            .into_iter()
+            .filter_map(|sequenced| match sequenced.event {
+                ChainEvent::Invoice {
+                    status, confirmed, ..
+                } => Some((status, confirmed)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (InvoiceStatus::Paid, 0),
+                (InvoiceStatus::Pending, 0),
+                (InvoiceStatus::Paid, 50_000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_payment_after_expiry_is_reported() {
+        let memory = MemoryPublisher::new();
+        let worker = sync_worker(&memory, 1);
+        let script = wallet_script(&worker, 0);
+        let address = Address::from_script(&script, Network::Regtest).unwrap();
+        let id = worker
+            .invoices
+            .create(&address.to_string(), 50_000, now() - 60)
+            .unwrap();
+
+        worker.update_invoices().unwrap();
+        let invoice = worker.invoices.get(id).unwrap().unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Expired);
+        assert_eq!(worker.invoices.list_open().unwrap().len(), 1);
+
+        // Paid late, still within the grace period
+        mine(&worker, tip(&worker), vec![payment(script, 50_000)], 0);
+        worker.update_invoices().unwrap();
+        let invoice = worker.invoices.get(id).unwrap().unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Paid);
+        assert_eq!((invoice.received, invoice.confirmed), (50_000, 50_000));
+
+        let statuses: Vec<_> = memory
+            .events()
+            .into_iter()
+            .filter_map(|sequenced| match sequenced.event {
+                ChainEvent::Invoice { status, .. } => Some(status),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(statuses, vec![InvoiceStatus::Expired, InvoiceStatus::Paid]);
+    }
+
+    #[test]
+    fn test_create_invoice_rejects_overflowing_expiry() {
+        let wallet = BitServWallet::new(
+            &WalletPaths::new(temp_dir("invoices-expiry"), "wallet").unwrap(),
+            "your-secure-invoices-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(MemoryPublisher::new()),
+            Vec::new(),
+        );
+        assert!(wallet
+            .create_invoice(50000, Duration::from_secs(u64::MAX))
+            .is_err());
+    }
+}
@@ -0,0 +1,75 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::memory_db;
+    use bitserv::Ledger;
+
+    fn ledger() -> Ledger {
+        Ledger::new(memory_db()).unwrap()
+    }
+
+    #[test]
+    fn test_credit_deposit_once() {
+        let ledger = ledger();
+        let credits = vec![("alice".to_string(), 50000), ("bob".to_string(), 20000)];
+        ledger.credit_deposit("txid1", &credits).unwrap();
+        // Crediting the same deposit again is a no-op
+        ledger.credit_deposit("txid1", &credits).unwrap();
+
+        assert_eq!(ledger.balance("alice").unwrap(), 50000);
+        assert_eq!(ledger.balance("bob").unwrap(), 20000);
+        assert_eq!(ledger.balance("carol").unwrap(), 0);
+
+        let history = ledger.history("alice", 10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].kind, "deposit");
+        assert_eq!(history[0].reference, "txid1");
+        assert_eq!(history[0].amount, 50000);
+    }
+
+    #[test]
+    fn test_reverse_and_recredit_deposit() {
+        let ledger = ledger();
+        let credits = vec![("alice".to_string(), 50000)];
+        ledger.credit_deposit("txid1", &credits).unwrap();
+
+        ledger.reverse_deposit("txid1").unwrap();
+        assert_eq!(ledger.balance("alice").unwrap(), 0);
+        // Reversing an uncredited deposit is a no-op
+        ledger.reverse_deposit("txid1").unwrap();
+        assert_eq!(ledger.balance("alice").unwrap(), 0);
+
+        // The deposit confirmed again in the new chain
+        ledger.credit_deposit("txid1", &credits).unwrap();
+        assert_eq!(ledger.balance("alice").unwrap(), 50000);
+
+        let kinds: Vec<_> = ledger
+            .history("alice", 10)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.kind)
+            .collect();
+        assert_eq!(kinds, vec!["deposit", "deposit_reversal", "deposit"]);
+    }
+
+    #[test]
+    fn test_debit_withdrawal() {
+        let ledger = ledger();
+        ledger
+            .credit_deposit("txid1", &[("alice".to_string(), 50000)])
+            .unwrap();
+
+        ledger.debit_withdrawal("alice", 1, 30000).unwrap();
+        assert_eq!(ledger.balance("alice").unwrap(), 20000);
+
+        // Can't withdraw more than the remaining balance
+        assert!(ledger.debit_withdrawal("alice", 2, 30000).is_err());
+        assert_eq!(ledger.balance("alice").unwrap(), 20000);
+
+        let latest = &ledger.history("alice", 1).unwrap()[0];
+        assert_eq!(latest.kind, "withdrawal");
+        assert_eq!(latest.reference, "1");
+        assert_eq!(latest.amount, -30000);
+    }
+}
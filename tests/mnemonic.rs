@@ -0,0 +1,285 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::temp_dir;
+    use bdk_wallet::bitcoin::Network;
+    use bitserv::{
+        pubsub::NoopPublisher, Argon2Variant, BitServWallet, KdfParams, MnemonicStorage, Seed,
+        WalletPaths,
+    };
+    use chacha20poly1305::{
+        aead::{Aead, KeyInit},
+        ChaCha20Poly1305, Nonce,
+    };
+    use serde_json::{json, Value};
+    use std::{fs, path::PathBuf};
+
+    const MNEMONIC: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    const CHEAP_KDF: KdfParams = KdfParams {
+        variant: Argon2Variant::Argon2id,
+        memory: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    fn storage_path(name: &str) -> PathBuf {
+        temp_dir(name).join("mnemonic.dat")
+    }
+
+    #[test]
+    fn test_change_password() {
+        let path = storage_path("mnemonic-change");
+        let storage = MnemonicStorage::new(path.clone());
+        storage.save_mnemonic(MNEMONIC, "old").unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+
+        storage.change_password("old", "new").unwrap();
+
+        assert_eq!(storage.load_mnemonic("new").unwrap(), MNEMONIC);
+        assert!(storage.load_mnemonic("old").is_err());
+        // Fresh salt and nonce
+        assert_ne!(fs::read_to_string(&path).unwrap(), before);
+        assert!(!path.with_extension("dat.tmp").exists());
+    }
+
+    #[test]
+    fn test_change_password_with_wrong_password() {
+        let path = storage_path("mnemonic-wrong");
+        let storage = MnemonicStorage::new(path.clone());
+        storage.save_mnemonic(MNEMONIC, "old").unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+
+        assert!(storage.change_password("wrong", "new").is_err());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        assert_eq!(storage.load_mnemonic("old").unwrap(), MNEMONIC);
+    }
+
+    #[test]
+    fn test_kdf_params_are_stored() {
+        let path = storage_path("mnemonic-kdf");
+        let storage = MnemonicStorage::new(path.clone()).with_kdf(CHEAP_KDF);
+        storage.save_mnemonic(MNEMONIC, "password").unwrap();
+
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 2);
+        assert_eq!(json["kdf"]["variant"], "argon2id");
+        assert_eq!(json["kdf"]["memory"], 1024);
+        assert_eq!(json["kdf"]["iterations"], 1);
+        assert_eq!(json["kdf"]["parallelism"], 1);
+
+        // Files can be read whatever parameters are configured for new files
+        let other = MnemonicStorage::new(path);
+        assert_eq!(other.load_mnemonic("password").unwrap(), MNEMONIC);
+    }
+
+    #[test]
+    fn test_unlock_upgrades_legacy_file() {
+        let path = storage_path("mnemonic-legacy");
+        // Files written before the format was versioned only had the ciphertext,
+        // nonce and salt of the bare mnemonic, with the key derived by Argon2's defaults
+        let salt = [1u8; 32];
+        let nonce = [2u8; 12];
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(b"password", &salt, &mut key)
+            .unwrap();
+        let encrypted_data = ChaCha20Poly1305::new_from_slice(&key)
+            .unwrap()
+            .encrypt(Nonce::from_slice(&nonce), MNEMONIC.as_bytes())
+            .unwrap();
+        let legacy = json!({"encrypted_data": encrypted_data, "nonce": nonce, "salt": salt});
+        fs::write(&path, legacy.to_string()).unwrap();
+
+        let storage = MnemonicStorage::new(path.clone()).with_kdf(CHEAP_KDF);
+        assert_eq!(storage.unlock("password").unwrap().mnemonic, MNEMONIC);
+
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["version"], 2);
+        // The weaker configured parameters don't downgrade the file
+        assert_eq!(json["kdf"]["memory"], KdfParams::default().memory);
+        assert_eq!(json["kdf"]["iterations"], KdfParams::default().iterations);
+        assert_eq!(storage.load_mnemonic("password").unwrap(), MNEMONIC);
+    }
+
+    #[test]
+    fn test_unlock_only_strengthens_kdf() {
+        let path = storage_path("mnemonic-strengthen");
+        MnemonicStorage::new(path.clone())
+            .with_kdf(CHEAP_KDF)
+            .save_mnemonic(MNEMONIC, "password")
+            .unwrap();
+
+        let stronger = KdfParams {
+            memory: 2048,
+            iterations: 2,
+            ..CHEAP_KDF
+        };
+        let storage = MnemonicStorage::new(path.clone()).with_kdf(stronger);
+        assert_eq!(storage.unlock("password").unwrap().mnemonic, MNEMONIC);
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["kdf"]["memory"], 2048);
+        assert_eq!(json["kdf"]["iterations"], 2);
+
+        // Weaker in any dimension leaves the file alone
+        let before = fs::read_to_string(&path).unwrap();
+        let mixed = KdfParams {
+            memory: 4096,
+            iterations: 1,
+            ..CHEAP_KDF
+        };
+        for kdf in [CHEAP_KDF, mixed] {
+            let storage = MnemonicStorage::new(path.clone()).with_kdf(kdf);
+            assert_eq!(storage.unlock("password").unwrap().mnemonic, MNEMONIC);
+            assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        }
+
+        // Nor does changing the password weaken it
+        let storage = MnemonicStorage::new(path.clone()).with_kdf(CHEAP_KDF);
+        storage.change_password("password", "new").unwrap();
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["kdf"]["memory"], 2048);
+        assert_eq!(storage.load_mnemonic("new").unwrap(), MNEMONIC);
+    }
+
+    #[test]
+    fn test_kdf_minimum_costs() {
+        assert!(KdfParams::default().check_minimum().is_ok());
+        assert!(CHEAP_KDF.check_minimum().is_err());
+        let few_iterations = KdfParams {
+            iterations: 1,
+            ..KdfParams::default()
+        };
+        assert!(few_iterations.check_minimum().is_err());
+    }
+
+    #[test]
+    fn test_unlock_with_wrong_password_keeps_file() {
+        let path = storage_path("mnemonic-unlock-wrong");
+        let storage = MnemonicStorage::new(path.clone()).with_kdf(CHEAP_KDF);
+        storage.save_mnemonic(MNEMONIC, "password").unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+
+        assert!(storage.unlock("wrong").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    }
+
+    #[test]
+    fn test_restore() {
+        let path = storage_path("mnemonic-restore");
+        let storage = MnemonicStorage::new(path).with_kdf(CHEAP_KDF);
+        // Extra whitespace is normalized away
+        storage
+            .restore(
+                &format!("  {}  ", MNEMONIC),
+                Some("cold storage"),
+                "password",
+            )
+            .unwrap();
+
+        let seed = storage.unlock("password").unwrap();
+        assert_eq!(
+            seed,
+            Seed {
+                mnemonic: MNEMONIC.to_string(),
+                passphrase: Some("cold storage".to_string()),
+            }
+        );
+
+        // Never overwrites an existing mnemonic
+        assert!(storage.restore(MNEMONIC, None, "password").is_err());
+    }
+
+    #[test]
+    fn test_restore_word_counts() {
+        let words_24 = "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo \
+                        zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote";
+        for (name, mnemonic, valid) in [
+            ("mnemonic-restore-24", words_24, true),
+            ("mnemonic-restore-11", "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about", false),
+            ("mnemonic-restore-checksum", "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon", false),
+        ] {
+            let storage = MnemonicStorage::new(storage_path(name)).with_kdf(CHEAP_KDF);
+            assert_eq!(storage.restore(mnemonic, None, "password").is_ok(), valid, "{}", name);
+            assert_eq!(storage.exists(), valid);
+        }
+    }
+
+    #[test]
+    fn test_load_or_create_with_wrong_password_fails() {
+        let path = storage_path("mnemonic-load-or-create");
+        let storage = MnemonicStorage::new(path.clone()).with_kdf(CHEAP_KDF);
+        let seed = storage.load_or_create_by_password("password").unwrap();
+        assert_eq!(seed.mnemonic.split_whitespace().count(), 12);
+
+        assert!(storage.load_or_create_by_password("wrong").is_err());
+        assert_eq!(
+            storage.load_or_create_by_password("password").unwrap(),
+            seed
+        );
+    }
+
+    #[test]
+    fn test_supplied_passphrase() {
+        let seed = Seed {
+            mnemonic: MNEMONIC.to_string(),
+            passphrase: None,
+        };
+        let supplied = seed
+            .clone()
+            .with_passphrase(Some("cold storage".to_string()))
+            .unwrap();
+        assert_eq!(supplied.passphrase.as_deref(), Some("cold storage"));
+        assert_eq!(seed.clone().with_passphrase(None).unwrap(), seed);
+
+        // A stored passphrase can be confirmed but not replaced
+        assert_eq!(
+            supplied
+                .clone()
+                .with_passphrase(Some("cold storage".to_string()))
+                .unwrap(),
+            supplied
+        );
+        assert!(supplied.with_passphrase(Some("other".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_passphrase_derives_distinct_wallet() {
+        let data_dir = storage_path("mnemonic-wallets")
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        // Address 0 of a wallet restored with the `stored` passphrase
+        // and opened with the `supplied` one
+        let address = |name: &str, stored: Option<&str>, supplied: Option<&str>| {
+            let paths = WalletPaths::new(&data_dir, name).unwrap();
+            MnemonicStorage::new(paths.mnemonic_path.clone())
+                .with_kdf(CHEAP_KDF)
+                .restore(MNEMONIC, stored, "password")
+                .unwrap();
+            let wallet = BitServWallet::new(
+                &paths,
+                "password",
+                supplied.map(str::to_string),
+                CHEAP_KDF,
+                Network::Regtest,
+                6,
+                Box::new(NoopPublisher),
+                Vec::new(),
+            );
+            wallet.get_receiving_address_by_index(0)
+        };
+
+        let plain = address("plain", None, None);
+        let with_passphrase = address("passphrase", Some("cold storage"), None);
+        let again = address("passphrase-again", Some("cold storage"), None);
+        let supplied = address("passphrase-supplied", None, Some("cold storage"));
+
+        assert_ne!(plain, with_passphrase);
+        assert_eq!(with_passphrase, again);
+        assert_eq!(with_passphrase, supplied);
+    }
+}
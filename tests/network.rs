@@ -0,0 +1,74 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{temp_dir, MockEsplora};
+    use bdk_wallet::bitcoin::Network;
+    use bitserv::{
+        config::{self, ChainBackend},
+        pubsub::NoopPublisher,
+        BitServWallet, Client, KdfParams, WalletPaths,
+    };
+
+    #[test]
+    fn test_parse_network() {
+        assert_eq!(config::parse_network("mainnet"), Some(Network::Bitcoin));
+        assert_eq!(config::parse_network("bitcoin"), Some(Network::Bitcoin));
+        assert_eq!(config::parse_network("testnet"), Some(Network::Testnet));
+        assert_eq!(config::parse_network("testnet4"), Some(Network::Testnet4));
+        assert_eq!(config::parse_network("signet"), Some(Network::Signet));
+        assert_eq!(config::parse_network("regtest"), Some(Network::Regtest));
+        assert_eq!(config::parse_network("Regtest"), None);
+        assert_eq!(config::parse_network(""), None);
+    }
+
+    #[test]
+    fn test_parse_chain_backend() {
+        assert_eq!(config::parse_chain_backend("rpc"), Some(ChainBackend::Rpc));
+        assert_eq!(
+            config::parse_chain_backend("electrum"),
+            Some(ChainBackend::Electrum)
+        );
+        assert_eq!(
+            config::parse_chain_backend("esplora"),
+            Some(ChainBackend::Esplora)
+        );
+        // A typo must not silently select rpc
+        assert_eq!(config::parse_chain_backend("esplorra"), None);
+        assert_eq!(config::parse_chain_backend(""), None);
+    }
+
+    #[test]
+    fn test_init_accepts_matching_chain() {
+        let esplora = MockEsplora::start();
+        let mut wallet = BitServWallet::new(
+            &WalletPaths::new(temp_dir("network-regtest"), "wallet").unwrap(),
+            "your-secure-network-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(NoopPublisher),
+            Vec::new(),
+        );
+        wallet.init(&Client::new_esplora(&esplora.url()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Chain backend is not on testnet")]
+    fn test_init_rejects_other_chain() {
+        // The mock serves the regtest chain
+        let esplora = MockEsplora::start();
+        let mut wallet = BitServWallet::new(
+            &WalletPaths::new(temp_dir("network-testnet"), "wallet").unwrap(),
+            "your-secure-network-password",
+            None,
+            KdfParams::default(),
+            Network::Testnet,
+            6,
+            Box::new(NoopPublisher),
+            Vec::new(),
+        );
+        wallet.init(&Client::new_esplora(&esplora.url()));
+    }
+}
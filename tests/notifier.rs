@@ -0,0 +1,39 @@
+#[cfg(test)]
+mod tests {
+    use bitserv::BlockNotifier;
+    use std::{thread, time::Duration};
+    use zmq::Context;
+
+    #[test]
+    fn test_notifier_wakes_on_notification() {
+        // Stand in for bitcoind's zmqpubhashblock socket
+        let context = Context::new();
+        let publisher = context.socket(zmq::PUB).unwrap();
+        publisher.bind("tcp://127.0.0.1:28390").unwrap();
+
+        let notifier = BlockNotifier::new("tcp://127.0.0.1:28390").unwrap();
+        // Give some time for the connection to establish
+        thread::sleep(Duration::from_millis(500));
+
+        // Silent socket falls through after the timeout
+        assert!(!notifier.wait(Duration::from_millis(100)).unwrap());
+
+        publisher
+            .send_multipart(["hashblock".as_bytes(), &[0u8; 32], &[0u8; 4]], 0)
+            .unwrap();
+        publisher
+            .send_multipart(["rawtx".as_bytes(), &[0u8; 60], &[1u8, 0, 0, 0]], 0)
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(notifier.wait(Duration::from_secs(5)).unwrap());
+
+        // Both notifications were drained by the first wait
+        assert!(!notifier.wait(Duration::from_millis(100)).unwrap());
+
+        // Topics bitserv doesn't subscribe to are ignored
+        publisher
+            .send_multipart(["sequence".as_bytes(), &[0u8; 33]], 0)
+            .unwrap();
+        assert!(!notifier.wait(Duration::from_millis(100)).unwrap());
+    }
+}
@@ -0,0 +1,80 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::temp_dir;
+    use bitserv::WalletPaths;
+    use sha2::{Digest, Sha256};
+    use std::fs;
+
+    fn legacy_hash(password: &str) -> String {
+        hex::encode(&Sha256::digest(password.as_bytes())[..16])
+    }
+
+    #[test]
+    fn test_paths_are_named_after_the_wallet() {
+        let data_dir = temp_dir("paths-layout");
+        let paths = WalletPaths::new(&data_dir, "main").unwrap();
+
+        assert!(data_dir.join("main").is_dir());
+        assert_eq!(
+            paths.mnemonic_path,
+            data_dir.join("main").join("mnemonic.dat")
+        );
+        assert_eq!(
+            paths.wallet_path,
+            data_dir.join("main").join("wallet.sqlite")
+        );
+
+        assert!(WalletPaths::new(&data_dir, "").is_err());
+        assert!(WalletPaths::new(&data_dir, "..").is_err());
+        assert!(WalletPaths::new(&data_dir, "a/b").is_err());
+    }
+
+    #[test]
+    fn test_migrate_legacy_moves_files() {
+        let data_dir = temp_dir("paths-migrate");
+        let legacy_dir = data_dir.join("pers");
+        fs::create_dir_all(&legacy_dir).unwrap();
+        let hash = legacy_hash("secret");
+        fs::write(
+            legacy_dir.join(format!("mnemonic_{}.dat", hash)),
+            "mnemonic",
+        )
+        .unwrap();
+        fs::write(legacy_dir.join(format!("wallet_{}.sqlite", hash)), "wallet").unwrap();
+        // Files of a wallet with another password stay where they are
+        let other = legacy_hash("other");
+        fs::write(legacy_dir.join(format!("mnemonic_{}.dat", other)), "other").unwrap();
+
+        let paths = WalletPaths::new(&data_dir, "main").unwrap();
+        assert!(paths.migrate_legacy(&legacy_dir, "secret").unwrap());
+
+        assert_eq!(
+            fs::read_to_string(&paths.mnemonic_path).unwrap(),
+            "mnemonic"
+        );
+        assert_eq!(fs::read_to_string(&paths.wallet_path).unwrap(), "wallet");
+        assert!(!legacy_dir.join(format!("mnemonic_{}.dat", hash)).exists());
+        assert!(legacy_dir.join(format!("mnemonic_{}.dat", other)).exists());
+
+        // Runs only once
+        assert!(!paths.migrate_legacy(&legacy_dir, "secret").unwrap());
+    }
+
+    #[test]
+    fn test_migrate_legacy_keeps_existing_wallet() {
+        let data_dir = temp_dir("paths-existing");
+        let legacy_dir = data_dir.join("pers");
+        fs::create_dir_all(&legacy_dir).unwrap();
+        let legacy_mnemonic = legacy_dir.join(format!("mnemonic_{}.dat", legacy_hash("secret")));
+        fs::write(&legacy_mnemonic, "legacy").unwrap();
+
+        let paths = WalletPaths::new(&data_dir, "main").unwrap();
+        fs::write(&paths.mnemonic_path, "current").unwrap();
+
+        assert!(!paths.migrate_legacy(&legacy_dir, "secret").unwrap());
+        assert_eq!(fs::read_to_string(&paths.mnemonic_path).unwrap(), "current");
+        assert!(legacy_mnemonic.exists());
+    }
+}
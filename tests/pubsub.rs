@@ -1,21 +1,43 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
-    use bitserv::pubsub::{ChainEvent, Publisher};
+    use crate::common::temp_dir;
+    use bdk_wallet::bitcoin::Network;
+    use bdk_wallet::{rusqlite::Connection, KeychainKind};
+    use bitserv::{
+        pubsub::{
+            ChainEvent, EventBus, EventFilter, MemoryPublisher, Outbox, SequencedEvent,
+            ZmqPublisher,
+        },
+        BitServWallet, KdfParams, WalletPaths,
+    };
     use lazy_static::lazy_static;
-    use std::{sync::Arc, thread, time::Duration};
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex as StdMutex},
+        thread,
+        time::Duration,
+    };
     use tokio::sync::Mutex;
     use zmq::{Context, Socket};
 
     struct TestContext {
-        publisher: Publisher,
+        publisher: EventBus,
         subscriber: Socket,
+        outbox: Outbox,
     }
 
     impl TestContext {
         fn new() -> Self {
             println!("Creating test context...");
             // Create publisher
-            let publisher = Publisher::new("tcp://*:5556").expect("Failed to create publisher");
+            let conn = Connection::open_in_memory().expect("Failed to open database");
+            let outbox =
+                Outbox::new(Arc::new(StdMutex::new(conn))).expect("Failed to create outbox");
+            let zmq_publisher =
+                ZmqPublisher::new("tcp://*:5556").expect("Failed to create publisher");
+            let publisher = EventBus::new(Box::new(zmq_publisher), outbox.clone());
             println!("Publisher created");
 
             // Create subscriber
@@ -42,6 +64,7 @@ mod tests {
             TestContext {
                 publisher,
                 subscriber,
+                outbox,
             }
         }
 
@@ -54,6 +77,10 @@ mod tests {
         }
 
         fn receive_event(&self) -> ChainEvent {
+            self.receive_sequenced_event().event
+        }
+
+        fn receive_sequenced_event(&self) -> SequencedEvent {
             println!("Waiting to receive event...");
             // Receive the topic first
             let topic = self
@@ -71,7 +98,13 @@ mod tests {
                 .expect("Invalid message format");
             println!("Received message: {}", msg);
 
-            serde_json::from_str(&msg).expect("Failed to parse event")
+            let event: SequencedEvent = serde_json::from_str(&msg).expect("Failed to parse event");
+            assert_eq!(
+                topic,
+                event.event.topic(),
+                "Event sent under the wrong topic"
+            );
+            event
         }
     }
 
@@ -103,17 +136,6 @@ mod tests {
         let received_event = context.receive_event();
         println!("Received event");
 
-        loop {
-            let event = ChainEvent::NewTransaction {
-                txid: "abc123".to_string(),
-                amount: 100000,
-                confirmations: 1,
-            };
-            context.publish_event(event);
-            println!("published event");
-            thread::sleep(Duration::from_millis(2000));
-        }
-
         match received_event {
             ChainEvent::NewTransaction {
                 txid,
@@ -138,6 +160,8 @@ mod tests {
         // Create and publish a new address event
         let event = ChainEvent::NewAddress {
             address: "bc1qxxx...".to_string(),
+            index: 0,
+            keychain: KeychainKind::External,
         };
         context.publish_event(event);
 
@@ -146,7 +170,7 @@ mod tests {
         println!("Received event");
 
         match received_event {
-            ChainEvent::NewAddress { address } => {
+            ChainEvent::NewAddress { address, .. } => {
                 assert_eq!(address, "bc1qxxx...");
             }
             _ => panic!("Received wrong event type"),
@@ -165,8 +189,10 @@ mod tests {
             ("address1".to_string(), 50000, "txid1".to_string()),
             ("address2".to_string(), 75000, "txid2".to_string()),
         ];
+        let customers = BTreeMap::from([("address1".to_string(), "customer1".to_string())]);
         let event = ChainEvent::NewDeposits {
             deposits: deposits.clone(),
+            customers: customers.clone(),
         };
         context.publish_event(event);
 
@@ -177,10 +203,194 @@ mod tests {
         match received_event {
             ChainEvent::NewDeposits {
                 deposits: received_deposits,
+                customers: received_customers,
             } => {
                 assert_eq!(received_deposits, deposits);
+                assert_eq!(received_customers, customers);
             }
             _ => panic!("Received wrong event type"),
         }
     }
+
+    #[tokio::test]
+    async fn test_publish_pending_deposits() {
+        println!("Starting test_publish_pending_deposits");
+        println!("Acquiring context lock...");
+        let context = TEST_CONTEXT.lock().await;
+        println!("Context lock acquired");
+
+        // Create and publish a pending deposits event
+        let deposits = vec![("address1".to_string(), 50000, "txid1".to_string())];
+        let event = ChainEvent::PendingDeposits {
+            deposits: deposits.clone(),
+        };
+        context.publish_event(event);
+
+        // Receive and verify the event
+        let received_event = context.receive_event();
+        println!("Received event");
+
+        match received_event {
+            ChainEvent::PendingDeposits {
+                deposits: received_deposits,
+            } => {
+                assert_eq!(received_deposits, deposits);
+            }
+            _ => panic!("Received wrong event type"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_published_events() {
+        println!("Starting test_replay_published_events");
+        println!("Acquiring context lock...");
+        let context = TEST_CONTEXT.lock().await;
+        println!("Context lock acquired");
+
+        context.publish_event(ChainEvent::NewAddress {
+            address: "address1".to_string(),
+            index: 0,
+            keychain: KeychainKind::External,
+        });
+        let first = context.receive_sequenced_event();
+
+        context.publish_event(ChainEvent::NewAddress {
+            address: "address2".to_string(),
+            index: 1,
+            keychain: KeychainKind::External,
+        });
+        let second = context.receive_sequenced_event();
+        assert!(second.seq > first.seq, "Sequence numbers should increase");
+
+        // Everything after the first event can be fetched again from the outbox
+        let replayed = context.outbox.since(first.seq, 100).unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].seq, second.seq);
+        match &replayed[0].event {
+            ChainEvent::NewAddress { address, .. } => assert_eq!(address, "address2"),
+            _ => panic!("Replayed wrong event type"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_subscribers_receive_published_events() {
+        println!("Starting test_stream_subscribers_receive_published_events");
+        println!("Acquiring context lock...");
+        let context = TEST_CONTEXT.lock().await;
+        println!("Context lock acquired");
+
+        let mut stream = context.publisher.subscribe();
+        context.publish_event(ChainEvent::NewDeposits {
+            deposits: vec![("address1".to_string(), 50000, "txid1".to_string())],
+            customers: BTreeMap::new(),
+        });
+        let published = context.receive_sequenced_event();
+
+        let streamed = stream.try_recv().expect("Event should be streamed");
+        assert_eq!(streamed.seq, published.seq);
+        assert_eq!(streamed.event.kind(), "dpsts");
+
+        let by_kind = EventFilter {
+            kinds: vec!["dpsts".to_string()],
+            address: None,
+        };
+        assert!(by_kind.matches(&streamed.event));
+
+        let by_address = EventFilter {
+            kinds: Vec::new(),
+            address: Some("address2".to_string()),
+        };
+        assert!(!by_address.matches(&streamed.event));
+        assert!(EventFilter::default().matches(&streamed.event));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_to_deposit_topics() {
+        println!("Starting test_subscribe_to_deposit_topics");
+        println!("Acquiring context lock...");
+        let context = TEST_CONTEXT.lock().await;
+        println!("Context lock acquired");
+
+        let deposits_subscriber = Context::new().socket(zmq::SUB).unwrap();
+        deposits_subscriber.connect("tcp://localhost:5556").unwrap();
+        deposits_subscriber.set_subscribe(b"deposits").unwrap();
+        thread::sleep(Duration::from_millis(500));
+
+        context.publish_event(ChainEvent::NewAddress {
+            address: "address1".to_string(),
+            index: 0,
+            keychain: KeychainKind::External,
+        });
+        context.receive_event();
+        context.publish_event(ChainEvent::CreditedDeposits {
+            deposits: vec![("address1".to_string(), 50000, "txid1".to_string())],
+        });
+        context.receive_event();
+
+        // Only the deposit event reaches the filtered subscriber
+        let topic = deposits_subscriber.recv_string(0).unwrap().unwrap();
+        assert_eq!(topic, "deposits.credited");
+        deposits_subscriber.recv_string(0).unwrap().unwrap();
+        assert_eq!(
+            deposits_subscriber.poll(zmq::POLLIN, 100).unwrap(),
+            0,
+            "No other events should arrive"
+        );
+    }
+
+    #[test]
+    fn test_memory_publisher() {
+        let conn = Connection::open_in_memory().unwrap();
+        let outbox = Outbox::new(Arc::new(StdMutex::new(conn))).unwrap();
+        let memory = MemoryPublisher::new();
+        let bus = EventBus::new(Box::new(memory.clone()), outbox);
+
+        bus.publish(ChainEvent::NewAddress {
+            address: "address1".to_string(),
+            index: 0,
+            keychain: KeychainKind::External,
+        })
+        .unwrap();
+
+        let events = memory.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].seq, 1);
+        assert_eq!(events[0].event.topic(), "address");
+    }
+
+    #[test]
+    fn test_reveal_address_publishes_new_address() {
+        let memory = MemoryPublisher::new();
+        let wallet = BitServWallet::new(
+            &WalletPaths::new(temp_dir("pubsub"), "wallet").unwrap(),
+            "your-secure-pubsub-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(memory.clone()),
+            Vec::new(),
+        );
+
+        let first = wallet.reveal_next_address().unwrap();
+        let second = wallet.reveal_next_address().unwrap();
+
+        let revealed: Vec<_> = memory
+            .events()
+            .into_iter()
+            .map(|event| match event.event {
+                ChainEvent::NewAddress {
+                    address,
+                    index,
+                    keychain,
+                } => (address, index, keychain),
+                _ => panic!("Published wrong event type"),
+            })
+            .collect();
+        assert_eq!(revealed.len(), 2);
+        assert_eq!(revealed[0].0, first);
+        assert_eq!(revealed[1].0, second);
+        assert_eq!(revealed[1].1, revealed[0].1 + 1);
+        assert_eq!(revealed[0].2, KeychainKind::External);
+    }
 }
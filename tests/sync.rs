@@ -0,0 +1,160 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{mine, payment, sync_worker, tip, wallet_script};
+    use bdk_wallet::bitcoin::{Address, Network, ScriptBuf};
+    use bitserv::pubsub::{ChainEvent, MemoryPublisher};
+
+    fn events(memory: &MemoryPublisher) -> Vec<ChainEvent> {
+        memory
+            .events()
+            .into_iter()
+            .map(|sequenced| sequenced.event)
+            .collect()
+    }
+
+    #[test]
+    fn test_mempool_deposits_published_once() {
+        let memory = MemoryPublisher::new();
+        let worker = sync_worker(&memory, 6);
+        let tx = payment(wallet_script(&worker, 0), 50_000);
+        let txid = tx.compute_txid().to_string();
+
+        worker.apply_mempool(vec![(tx.clone(), 1)]).unwrap();
+        // The same transaction again, as a later poll of the mempool returns it
+        worker.apply_mempool(vec![(tx, 2)]).unwrap();
+
+        let pending: Vec<_> = events(&memory)
+            .into_iter()
+            .filter_map(|event| match event {
+                ChainEvent::PendingDeposits { deposits } => Some(deposits),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].len(), 1);
+        assert_eq!(pending[0][0].1, 50_000);
+        assert_eq!(pending[0][0].2, txid);
+    }
+
+    #[test]
+    fn test_mempool_ignores_foreign_transactions() {
+        let memory = MemoryPublisher::new();
+        let worker = sync_worker(&memory, 6);
+        let tx = payment(ScriptBuf::new(), 50_000);
+
+        worker.apply_mempool(vec![(tx, 1)]).unwrap();
+
+        assert!(events(&memory).is_empty());
+    }
+
+    #[test]
+    fn test_confirmations_progress_and_credit_once() {
+        let memory = MemoryPublisher::new();
+        let worker = sync_worker(&memory, 3);
+        let script = wallet_script(&worker, 0);
+        let address = Address::from_script(&script, Network::Regtest).unwrap();
+        worker
+            .customers
+            .assign("alice", &address.to_string(), 0)
+            .unwrap();
+        let tx = payment(script, 50_000);
+        let txid = tx.compute_txid().to_string();
+
+        let block = mine(&worker, tip(&worker), vec![tx], 0);
+        worker.update_confirmations().unwrap();
+        assert_eq!(worker.ledger.balance("alice").unwrap(), 0);
+
+        // Two blocks between syncs still publish every count up to the threshold
+        let block = mine(&worker, block, vec![], 0);
+        mine(&worker, block, vec![], 0);
+        worker.update_confirmations().unwrap();
+
+        let confirmations: Vec<u32> = events(&memory)
+            .into_iter()
+            .filter_map(|event| match event {
+                ChainEvent::NewTransaction {
+                    txid: event_txid,
+                    confirmations,
+                    ..
+                } if event_txid == txid => Some(confirmations),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(confirmations, vec![1, 2, 3]);
+
+        let credited: Vec<_> = events(&memory)
+            .into_iter()
+            .filter_map(|event| match event {
+                ChainEvent::CreditedDeposits { deposits } => Some(deposits),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            credited,
+            vec![vec![(address.to_string(), 50_000, txid.clone())]]
+        );
+        assert_eq!(worker.ledger.balance("alice").unwrap(), 50_000);
+        assert!(worker.deposits.credited_txids().unwrap().contains(&txid));
+
+        // Nothing changes on the next sync, and the deposit isn't credited twice
+        let published = memory.events().len();
+        mine(&worker, tip(&worker), vec![], 0);
+        worker.update_confirmations().unwrap();
+        assert_eq!(memory.events().len(), published);
+        assert_eq!(worker.ledger.balance("alice").unwrap(), 50_000);
+    }
+
+    #[test]
+    fn test_reorg_reverts_credited_deposit() {
+        let memory = MemoryPublisher::new();
+        let worker = sync_worker(&memory, 1);
+        let script = wallet_script(&worker, 0);
+        let address = Address::from_script(&script, Network::Regtest).unwrap();
+        worker
+            .customers
+            .assign("alice", &address.to_string(), 0)
+            .unwrap();
+        let tx = payment(script, 50_000);
+        let txid = tx.compute_txid().to_string();
+
+        let genesis = tip(&worker);
+        mine(&worker, genesis, vec![tx], 0);
+        worker.update_confirmations().unwrap();
+        assert_eq!(worker.ledger.balance("alice").unwrap(), 50_000);
+
+        // A competing block at the same height without the deposit
+        mine(&worker, genesis, vec![], 1);
+
+        let reverted: Vec<_> = events(&memory)
+            .into_iter()
+            .filter_map(|event| match event {
+                ChainEvent::RevertedDeposits { deposits } => Some(deposits),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            reverted,
+            vec![vec![(address.to_string(), 50_000, txid.clone())]]
+        );
+        assert_eq!(worker.ledger.balance("alice").unwrap(), 0);
+        let deposit = worker.deposits.get(&txid).unwrap().unwrap();
+        assert_eq!(deposit.confirmations, 0);
+        assert!(!worker.deposits.credited_txids().unwrap().contains(&txid));
+    }
+
+    #[test]
+    fn test_extending_chain_reverts_nothing() {
+        let memory = MemoryPublisher::new();
+        let worker = sync_worker(&memory, 1);
+        let tx = payment(wallet_script(&worker, 0), 50_000);
+
+        let block = mine(&worker, tip(&worker), vec![tx], 0);
+        mine(&worker, block, vec![], 0);
+
+        assert!(!events(&memory)
+            .iter()
+            .any(|event| matches!(event, ChainEvent::RevertedDeposits { .. })));
+    }
+}
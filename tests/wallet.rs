@@ -1,8 +1,10 @@
 #[cfg(test)]
 mod tests {
     use bdk_bitcoind_rpc::bitcoincore_rpc::Auth;
-    use bdk_wallet::bitcoin::Network;
-    use bitserv::{BitServWallet, Client};
+    use bitserv::{
+        config, pubsub::NoopPublisher, BitServWallet, Client, Direction, KdfParams,
+        TransactionFilter, WalletPaths,
+    };
     use lazy_static::lazy_static;
     use std::sync::Arc;
     use tokio::sync::Mutex;
@@ -19,7 +21,16 @@ mod tests {
             let bitcoind_password = "mypassword";
             let auth = Auth::UserPass(bitcoind_username.to_string(), bitcoind_password.to_string());
             let client = Client::new_rpc(bitcoind_url, auth);
-            let mut wallet = BitServWallet::new("your-secure-password", Network::Regtest);
+            let mut wallet = BitServWallet::new(
+                &WalletPaths::new(config::data_dir(), "wallet").unwrap(),
+                "your-secure-password",
+                None,
+                KdfParams::default(),
+                config::network(),
+                6,
+                Box::new(NoopPublisher),
+                Vec::new(),
+            );
             wallet.init(&client);
 
             TestContext {
@@ -37,7 +48,7 @@ mod tests {
         }
 
         fn sync_wallet(&mut self) {
-            let client = self.create_client();
+            let client = self.client.take().unwrap_or_else(|| self.create_client());
             self.wallet.sync(client);
         }
     }
@@ -62,16 +73,13 @@ mod tests {
 
         // Test getting addresses
         let all_addresses = context.wallet.get_all_addresses();
-        assert!(
-            all_addresses.len() >= 0,
-            "Should return a list of addresses"
-        );
-
         let change_addresses = context.wallet.get_change_addresses();
-        assert!(
-            change_addresses.len() >= 0,
-            "Should return a list of change addresses"
-        );
+        for address in change_addresses {
+            assert!(
+                all_addresses.contains(&(address, true)),
+                "Change addresses should be listed as change in all addresses"
+            );
+        }
     }
 
     #[tokio::test]
@@ -87,4 +95,102 @@ mod tests {
         let address = context.wallet.reveal_next_address().unwrap();
         assert!(!address.is_empty(), "Address should not be empty");
     }
+
+    #[tokio::test]
+    async fn test_list_transactions() {
+        let context = TEST_CONTEXT.lock().await;
+
+        let all = context
+            .wallet
+            .list_transactions(&TransactionFilter::default(), 0, 1000);
+        let heights: Vec<_> = all
+            .iter()
+            .map(|tx| tx.confirmation_height.unwrap_or(u32::MAX))
+            .collect();
+        assert!(
+            heights.windows(2).all(|pair| pair[0] >= pair[1]),
+            "Transactions should be listed newest first"
+        );
+
+        // Pages don't overlap
+        if all.len() >= 2 {
+            let second_page = context
+                .wallet
+                .list_transactions(&TransactionFilter::default(), 1, 1);
+            assert_eq!(second_page, vec![all[1].clone()]);
+        }
+
+        let incoming = context.wallet.list_transactions(
+            &TransactionFilter {
+                direction: Some(Direction::Incoming),
+                confirmed: Some(true),
+                ..Default::default()
+            },
+            0,
+            1000,
+        );
+        for tx in incoming {
+            assert_eq!(tx.direction, Direction::Incoming);
+            assert!(tx.amount > 0, "Incoming transactions should add coins");
+            assert!(tx.confirmation_height.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_transaction() {
+        let context = TEST_CONTEXT.lock().await;
+
+        assert!(context.wallet.get_transaction("not-a-txid").is_err());
+        let unknown = "0000000000000000000000000000000000000000000000000000000000000000";
+        assert!(context.wallet.get_transaction(unknown).unwrap().is_none());
+
+        let listed = context
+            .wallet
+            .list_transactions(&TransactionFilter::default(), 0, 1);
+        let Some(summary) = listed.first() else {
+            return;
+        };
+        let detail = context
+            .wallet
+            .get_transaction(&summary.txid)
+            .unwrap()
+            .expect("Listed transaction should have details");
+
+        assert_eq!(detail.txid, summary.txid);
+        assert_eq!(detail.fee, summary.fee);
+        assert_eq!(detail.received as i64 - detail.sent as i64, summary.amount);
+        // Everything we received went to outputs derived from our keychains
+        let ours: u64 = detail
+            .outputs
+            .iter()
+            .filter(|out| out.derivation.is_some())
+            .map(|out| out.amount)
+            .sum();
+        assert_eq!(ours, detail.received);
+        assert!(!detail.hex.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_freeze_utxo() {
+        let context = TEST_CONTEXT.lock().await;
+
+        let utxos = context.wallet.list_utxos().unwrap();
+        let total: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+        assert_eq!(total, context.wallet.balance().total().to_sat());
+
+        let Some(utxo) = utxos.first() else {
+            return;
+        };
+        context
+            .wallet
+            .freeze_utxo(&utxo.outpoint, Some("suspicious"))
+            .unwrap();
+        let frozen = context.wallet.list_utxos().unwrap();
+        assert!(frozen
+            .iter()
+            .any(|candidate| candidate.outpoint == utxo.outpoint && candidate.frozen));
+
+        assert!(context.wallet.unfreeze_utxo(&utxo.outpoint).unwrap());
+        assert!(!context.wallet.unfreeze_utxo(&utxo.outpoint).unwrap());
+    }
 }
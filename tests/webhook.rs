@@ -0,0 +1,195 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::memory_db;
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use bdk_wallet::KeychainKind;
+    use bitserv::pubsub::{
+        sign, ChainEvent, Outbox, RetryPolicy, SequencedEvent, WebhookDelivery, WebhookDispatcher,
+        WebhookEndpoint, SEQUENCE_HEADER, SIGNATURE_HEADER,
+    };
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    const SECRET: &str = "webhook-secret";
+
+    // Rejects the first request and requires a valid signature on every request
+    async fn receive(
+        State(requests): State<Arc<AtomicUsize>>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let attempt = requests.fetch_add(1, Ordering::SeqCst);
+
+        let expected = format!("sha256={}", sign(SECRET, &body));
+        let signature = headers.get(SIGNATURE_HEADER).and_then(|v| v.to_str().ok());
+        if signature != Some(expected.as_str()) || headers.get(SEQUENCE_HEADER).is_none() {
+            return StatusCode::UNAUTHORIZED;
+        }
+
+        if attempt == 0 {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    fn start_receiver(requests: Arc<AtomicUsize>) -> SocketAddr {
+        let app = Router::new()
+            .route("/hook", post(receive))
+            .with_state(requests);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                axum::serve(listener, app).await.unwrap();
+            });
+        });
+
+        address
+    }
+
+    #[test]
+    fn test_webhook_retries_until_delivered() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let address = start_receiver(requests.clone());
+        let url = format!("http://{}/hook", address);
+
+        let conn = memory_db();
+        let dispatcher = WebhookDispatcher::new(
+            conn,
+            vec![WebhookEndpoint {
+                url: url.clone(),
+                secret: SECRET.to_string(),
+            }],
+            RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(50),
+            },
+        )
+        .unwrap();
+
+        let event = SequencedEvent {
+            seq: 1,
+            event: ChainEvent::NewAddress {
+                address: "bcrt1qexample".to_string(),
+                index: 0,
+                keychain: KeychainKind::External,
+            },
+        };
+        dispatcher.dispatch(&event).unwrap();
+
+        let deliveries = wait_for(&dispatcher, 1, "delivered");
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].url, url);
+        assert_eq!(deliveries[0].status, "delivered");
+        assert_eq!(deliveries[0].attempts, 2);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_pending_deliveries_requeued_on_startup() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let address = start_receiver(requests.clone());
+        let endpoint = WebhookEndpoint {
+            url: format!("http://{}/hook", address),
+            secret: SECRET.to_string(),
+        };
+
+        let conn = memory_db();
+        let outbox = Outbox::new(conn.clone()).unwrap();
+        let event = ChainEvent::NewAddress {
+            address: "bcrt1qexample".to_string(),
+            index: 0,
+            keychain: KeychainKind::External,
+        };
+        let seq = outbox.append(&event).unwrap();
+
+        // The first attempt fails and the retry is far off, like a process that
+        // stops while a delivery is still pending
+        let stopped = WebhookDispatcher::new(
+            conn.clone(),
+            vec![endpoint.clone()],
+            RetryPolicy {
+                max_attempts: 2,
+                initial_backoff: Duration::from_secs(600),
+            },
+        )
+        .unwrap();
+        stopped.dispatch(&SequencedEvent { seq, event }).unwrap();
+        let deliveries = wait_for(&stopped, seq, "pending");
+        assert_eq!(deliveries[0].attempts, 1);
+
+        let restarted =
+            WebhookDispatcher::new(conn, vec![endpoint], RetryPolicy::default()).unwrap();
+        let deliveries = wait_for(&restarted, seq, "delivered");
+        assert_eq!(deliveries[0].attempts, 1);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_dispatcher_runs_inside_async_runtime() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let address = start_receiver(requests.clone());
+        let endpoint = WebhookEndpoint {
+            url: format!("http://{}/hook", address),
+            secret: SECRET.to_string(),
+        };
+
+        // The server creates the dispatcher from its async main
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let dispatcher = runtime.block_on(async {
+            WebhookDispatcher::new(
+                memory_db(),
+                vec![endpoint],
+                RetryPolicy {
+                    max_attempts: 3,
+                    initial_backoff: Duration::from_millis(50),
+                },
+            )
+            .unwrap()
+        });
+
+        let event = SequencedEvent {
+            seq: 1,
+            event: ChainEvent::NewAddress {
+                address: "bcrt1qexample".to_string(),
+                index: 0,
+                keychain: KeychainKind::External,
+            },
+        };
+        dispatcher.dispatch(&event).unwrap();
+        let deliveries = wait_for(&dispatcher, 1, "delivered");
+        assert_eq!(deliveries[0].status, "delivered");
+        runtime.block_on(async { drop(dispatcher) });
+    }
+
+    // Poll until the delivery of `seq` reaches `status`, after at least one attempt
+    fn wait_for(dispatcher: &WebhookDispatcher, seq: u64, status: &str) -> Vec<WebhookDelivery> {
+        let mut deliveries = Vec::new();
+        for _ in 0..50 {
+            deliveries = dispatcher.deliveries(seq).unwrap();
+            if deliveries
+                .iter()
+                .any(|d| d.status == status && d.attempts > 0)
+            {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        deliveries
+    }
+}
@@ -0,0 +1,368 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{memory_db, payment, temp_dir, MockEsplora};
+    use bdk_wallet::bitcoin::{hashes::Hash, Address, Network, ScriptBuf, WScriptHash};
+    use bitserv::{
+        pubsub::NoopPublisher, BitServWallet, Client, KdfParams, Ledger, WalletPaths,
+        WithdrawalQueue, WithdrawalStatus,
+    };
+
+    fn create_queue() -> WithdrawalQueue {
+        WithdrawalQueue::new(memory_db()).expect("Failed to create queue")
+    }
+
+    #[test]
+    fn test_enqueue_withdrawal() {
+        let queue = create_queue();
+
+        let id = queue.enqueue("address1", 50000).unwrap();
+        let withdrawal = queue.get(id).unwrap().expect("Withdrawal should exist");
+
+        assert_eq!(withdrawal.address, "address1");
+        assert_eq!(withdrawal.amount, 50000);
+        assert_eq!(withdrawal.status, WithdrawalStatus::Pending);
+        assert_eq!(withdrawal.txid, None);
+        assert!(queue.get(id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_withdrawal_lifecycle() {
+        let queue = create_queue();
+
+        let first = queue.enqueue("address1", 50000).unwrap();
+        let second = queue.enqueue("address2", 75000).unwrap();
+
+        let pending = queue.list_by_status(WithdrawalStatus::Pending).unwrap();
+        assert_eq!(
+            pending.iter().map(|w| w.id).collect::<Vec<_>>(),
+            vec![first, second]
+        );
+
+        queue.mark_broadcast(&[first, second], "txid1").unwrap();
+        assert!(queue
+            .list_by_status(WithdrawalStatus::Pending)
+            .unwrap()
+            .is_empty());
+
+        let broadcast = queue.list_by_status(WithdrawalStatus::Broadcast).unwrap();
+        assert_eq!(broadcast.len(), 2);
+        assert!(broadcast.iter().all(|w| w.txid.as_deref() == Some("txid1")));
+
+        queue.mark_confirmed(&[first]).unwrap();
+        assert_eq!(
+            queue.get(first).unwrap().unwrap().status,
+            WithdrawalStatus::Confirmed
+        );
+        assert_eq!(
+            queue.get(second).unwrap().unwrap().status,
+            WithdrawalStatus::Broadcast
+        );
+    }
+
+    #[test]
+    fn test_enqueue_debited_is_atomic() {
+        let conn = memory_db();
+        let queue = WithdrawalQueue::new(conn.clone()).unwrap();
+        let ledger = Ledger::new(conn).unwrap();
+        ledger
+            .credit_deposit("txid1", &[("alice".to_string(), 50000)])
+            .unwrap();
+
+        let id = queue.enqueue_debited("address1", 30000, "alice").unwrap();
+        assert_eq!(queue.get(id).unwrap().unwrap().amount, 30000);
+        assert_eq!(ledger.balance("alice").unwrap(), 20000);
+
+        // Rejected for the balance, and nothing left in the queue to be paid
+        assert!(queue.enqueue_debited("address2", 30000, "alice").is_err());
+        assert_eq!(
+            queue
+                .list_by_status(WithdrawalStatus::Pending)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(queue.get(id + 1).unwrap().is_none());
+        assert_eq!(ledger.balance("alice").unwrap(), 20000);
+    }
+
+    #[test]
+    fn test_cancel_refunds_debited_withdrawal() {
+        let conn = memory_db();
+        let queue = WithdrawalQueue::new(conn.clone()).unwrap();
+        let ledger = Ledger::new(conn).unwrap();
+        ledger
+            .credit_deposit("txid1", &[("alice".to_string(), 50000)])
+            .unwrap();
+
+        let id = queue.enqueue_debited("address1", 30000, "alice").unwrap();
+        let cancelled = queue.cancel(id).unwrap().unwrap();
+        assert_eq!(cancelled.status, WithdrawalStatus::Cancelled);
+        assert_eq!(
+            queue.get(id).unwrap().unwrap().status,
+            WithdrawalStatus::Cancelled
+        );
+        assert_eq!(ledger.balance("alice").unwrap(), 50000);
+        assert_eq!(
+            ledger.history("alice", 1).unwrap()[0].kind,
+            "withdrawal_refund"
+        );
+
+        // Cancelled withdrawals aren't paid, nor refunded twice
+        assert!(queue
+            .list_by_status(WithdrawalStatus::Pending)
+            .unwrap()
+            .is_empty());
+        assert!(queue.cancel(id).is_err());
+        assert_eq!(ledger.balance("alice").unwrap(), 50000);
+        assert!(queue.cancel(id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cancel_before_marking_aborts_batch() {
+        let conn = memory_db();
+        let queue = WithdrawalQueue::new(conn.clone()).unwrap();
+        let ledger = Ledger::new(conn).unwrap();
+        ledger
+            .credit_deposit("txid1", &[("alice".to_string(), 50000)])
+            .unwrap();
+        let first = queue.enqueue("address1", 20000).unwrap();
+        let second = queue.enqueue_debited("address2", 30000, "alice").unwrap();
+
+        // The batch is built from the pending list, and one payout is cancelled
+        // before the batch gets marked
+        let batch: Vec<_> = queue
+            .list_by_status(WithdrawalStatus::Pending)
+            .unwrap()
+            .iter()
+            .map(|w| w.id)
+            .collect();
+        queue.cancel(second).unwrap();
+
+        assert!(queue.mark_broadcast(&batch, "txid1").is_err());
+        // Reverting the failed batch leaves the cancelled payout alone
+        queue.mark_pending(&batch).unwrap();
+        let first = queue.get(first).unwrap().unwrap();
+        assert_eq!(first.status, WithdrawalStatus::Pending);
+        assert_eq!(first.txid, None);
+        assert_eq!(
+            queue.get(second).unwrap().unwrap().status,
+            WithdrawalStatus::Cancelled
+        );
+        assert_eq!(ledger.balance("alice").unwrap(), 50000);
+    }
+
+    #[test]
+    fn test_cancel_rejects_broadcast_withdrawal() {
+        let queue = create_queue();
+        let id = queue.enqueue("address1", 50000).unwrap();
+        queue.mark_broadcast(&[id], "txid1").unwrap();
+
+        assert!(queue.cancel(id).is_err());
+        assert_eq!(
+            queue.get(id).unwrap().unwrap().status,
+            WithdrawalStatus::Broadcast
+        );
+    }
+
+    /// Wallet holding one confirmed 100000 sat coin, connected to a mock Esplora
+    fn funded_wallet(name: &str) -> (BitServWallet, MockEsplora) {
+        let paths = WalletPaths::new(temp_dir(name), "wallet").unwrap();
+        let mut wallet = BitServWallet::new(
+            &paths,
+            "your-secure-withdrawals-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(NoopPublisher),
+            Vec::new(),
+        );
+
+        let esplora = MockEsplora::start();
+        let script = wallet
+            .address_script(&wallet.get_receiving_address_by_index(0))
+            .unwrap();
+        esplora.confirm(&payment(script.clone(), 100_000), &script);
+
+        let client = Client::new_esplora(&esplora.url());
+        wallet.init(&client);
+        wallet.sync(client);
+        assert_eq!(wallet.balance().confirmed.to_sat(), 100_000);
+        (wallet, esplora)
+    }
+
+    fn recipient(seed: u8) -> (String, ScriptBuf) {
+        let script = ScriptBuf::new_p2wsh(&WScriptHash::from_byte_array([seed; 32]));
+        let address = Address::from_script(&script, Network::Regtest).unwrap();
+        (address.to_string(), script)
+    }
+
+    #[test]
+    fn test_send_broadcasts_payment() {
+        let (wallet, esplora) = funded_wallet("withdrawals-send");
+        let (address, script) = recipient(1);
+
+        let txid = wallet.send(&address, 30_000, 2).unwrap();
+
+        let broadcast = esplora.broadcast();
+        assert_eq!(broadcast.len(), 1);
+        let tx = &broadcast[0];
+        assert_eq!(tx.compute_txid(), txid);
+        assert!(tx
+            .output
+            .iter()
+            .any(|out| out.script_pubkey == script && out.value.to_sat() == 30_000));
+
+        // The change stays in the wallet, minus the fee
+        let detail = wallet.get_transaction(&txid.to_string()).unwrap().unwrap();
+        let fee = detail.fee.unwrap();
+        assert!(detail.fee_rate.unwrap() > 1.9);
+        assert_eq!(wallet.balance().total().to_sat(), 100_000 - 30_000 - fee);
+
+        // Unknown network and unaffordable amounts are refused without broadcasting
+        assert!(wallet
+            .send("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", 1_000, 2)
+            .is_err());
+        assert!(wallet.send(&address, 1_000_000, 2).is_err());
+        assert_eq!(esplora.broadcast().len(), 1);
+    }
+
+    #[test]
+    fn test_process_withdrawals_pays_batch() {
+        let (wallet, esplora) = funded_wallet("withdrawals-batch");
+        let (first_address, first_script) = recipient(1);
+        let (second_address, second_script) = recipient(2);
+
+        let first = wallet
+            .queue_withdrawal(&first_address, 20_000, None)
+            .unwrap();
+        let second = wallet
+            .queue_withdrawal(&second_address, 25_000, None)
+            .unwrap();
+        wallet.process_withdrawals(2).unwrap();
+
+        let broadcast = esplora.broadcast();
+        assert_eq!(broadcast.len(), 1);
+        let tx = &broadcast[0];
+        for (script, amount) in [(first_script, 20_000), (second_script, 25_000)] {
+            assert!(tx
+                .output
+                .iter()
+                .any(|out| out.script_pubkey == script && out.value.to_sat() == amount));
+        }
+
+        let txid = tx.compute_txid().to_string();
+        for id in [first, second] {
+            let withdrawal = wallet.get_withdrawal(id).unwrap().unwrap();
+            assert_eq!(withdrawal.status, WithdrawalStatus::Broadcast);
+            assert_eq!(withdrawal.txid.as_deref(), Some(txid.as_str()));
+        }
+
+        // Nothing left to pay
+        wallet.process_withdrawals(2).unwrap();
+        assert_eq!(esplora.broadcast().len(), 1);
+    }
+
+    #[test]
+    fn test_queue_withdrawal_rejects_dust() {
+        let paths = WalletPaths::new(temp_dir("withdrawals-dust"), "wallet").unwrap();
+        let wallet = BitServWallet::new(
+            &paths,
+            "your-secure-withdrawals-password",
+            None,
+            KdfParams::default(),
+            Network::Regtest,
+            6,
+            Box::new(NoopPublisher),
+            Vec::new(),
+        );
+        // The dust limit of a P2WSH output is 330 sats
+        let (address, _) = recipient(1);
+
+        assert!(wallet.queue_withdrawal(&address, 0, None).is_err());
+        assert!(wallet.queue_withdrawal(&address, 329, None).is_err());
+        assert!(wallet
+            .queue_withdrawal("not an address", 10_000, None)
+            .is_err());
+        let id = wallet.queue_withdrawal(&address, 330, None).unwrap();
+        assert_eq!(wallet.get_withdrawal(id).unwrap().unwrap().amount, 330);
+    }
+
+    #[test]
+    fn test_failed_broadcast_keeps_withdrawals_pending() {
+        let (wallet, esplora) = funded_wallet("withdrawals-failed-broadcast");
+        let (address, _) = recipient(1);
+        let id = wallet.queue_withdrawal(&address, 20_000, None).unwrap();
+
+        esplora.reject_broadcasts(true);
+        assert!(wallet.process_withdrawals(2).is_err());
+        let withdrawal = wallet.get_withdrawal(id).unwrap().unwrap();
+        assert_eq!(withdrawal.status, WithdrawalStatus::Pending);
+        assert_eq!(withdrawal.txid, None);
+        // The coin reserved by the failed transaction can be spent again
+        assert_eq!(wallet.balance().confirmed.to_sat(), 100_000);
+
+        esplora.reject_broadcasts(false);
+        wallet.process_withdrawals(2).unwrap();
+        let withdrawal = wallet.get_withdrawal(id).unwrap().unwrap();
+        assert_eq!(withdrawal.status, WithdrawalStatus::Broadcast);
+        assert_eq!(
+            withdrawal.txid,
+            Some(esplora.broadcast()[0].compute_txid().to_string())
+        );
+    }
+
+    #[test]
+    fn test_uncertain_broadcast_keeps_withdrawals_broadcast() {
+        let (wallet, esplora) = funded_wallet("withdrawals-uncertain-broadcast");
+        let (address, _) = recipient(1);
+        let id = wallet.queue_withdrawal(&address, 20_000, None).unwrap();
+
+        esplora.lose_broadcast_responses(true);
+        assert!(wallet.process_withdrawals(2).is_err());
+        let txid = esplora.broadcast()[0].compute_txid().to_string();
+        let withdrawal = wallet.get_withdrawal(id).unwrap().unwrap();
+        assert_eq!(withdrawal.status, WithdrawalStatus::Broadcast);
+        assert_eq!(withdrawal.txid, Some(txid));
+
+        // The next batch doesn't pay it again
+        esplora.lose_broadcast_responses(false);
+        wallet.process_withdrawals(2).unwrap();
+        assert_eq!(esplora.broadcast().len(), 1);
+    }
+
+    #[test]
+    fn test_unaffordable_withdrawals_wait_for_funds() {
+        let (wallet, esplora) = funded_wallet("withdrawals-unaffordable");
+        let (first_address, first_script) = recipient(1);
+        let (second_address, _) = recipient(2);
+        let first = wallet
+            .queue_withdrawal(&first_address, 60_000, None)
+            .unwrap();
+        let second = wallet
+            .queue_withdrawal(&second_address, 60_000, None)
+            .unwrap();
+
+        // Only the oldest fits in the wallet's 100000 sats
+        wallet.process_withdrawals(2).unwrap();
+        let broadcast = esplora.broadcast();
+        assert_eq!(broadcast.len(), 1);
+        assert!(broadcast[0]
+            .output
+            .iter()
+            .any(|out| out.script_pubkey == first_script));
+        assert_eq!(
+            wallet.get_withdrawal(first).unwrap().unwrap().status,
+            WithdrawalStatus::Broadcast
+        );
+        let second = wallet.get_withdrawal(second).unwrap().unwrap();
+        assert_eq!(second.status, WithdrawalStatus::Pending);
+        assert_eq!(second.txid, None);
+
+        // A lone payout the wallet can't cover still fails
+        assert!(wallet.process_withdrawals(2).is_err());
+        assert_eq!(esplora.broadcast().len(), 1);
+    }
+}